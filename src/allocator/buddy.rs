@@ -0,0 +1,287 @@
+use super::Locked;
+use alloc::alloc::{
+    Layout,
+    GlobalAlloc,
+};
+use core::{
+    mem,
+    ptr::{
+        self,
+        NonNull,
+    },
+};
+
+/// The size of the smallest blocks in bytes
+const MIN_BLOCK_SIZE: usize = 16;
+
+/// The number of block sizes, each twice the previous one
+const ORDERS: usize = 32;
+
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// An allocator which splits the heap into blocks with power-of-two sizes
+///
+/// Each block is aligned to its size, so alignments up to the block size are
+/// satisfied without padding. A freed block is merged with its free buddy,
+/// the other half of the block they were split from.
+pub struct BuddyAllocator {
+    free_lists: [Option<NonNull<FreeBlock>>; ORDERS],
+    heap_size: usize,
+}
+
+// The free blocks are owned by the allocator alone
+unsafe impl Send for BuddyAllocator {}
+
+impl BuddyAllocator {
+    /// Creates an empty allocator. All alloc calls will return null.
+    pub const fn empty() -> Self {
+        BuddyAllocator {
+            free_lists: [None; ORDERS],
+            heap_size: 0,
+        }
+    }
+
+    /// Creates a new allocator with the given heap bounds.
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused.
+    pub unsafe fn new(heap_start: usize, heap_size: usize) -> Self {
+        let mut allocator = Self::empty();
+        allocator.init(heap_start, heap_size);
+
+        allocator
+    }
+
+    /// Initialize an empty allocator with the given heap bounds.
+    ///
+    /// The heap is split into the largest blocks aligned to their size.
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused. This method must be
+    /// called only once and on an empty allocator.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        let mut address = align_up(heap_start, MIN_BLOCK_SIZE);
+        let end = (heap_start + heap_size) & !(MIN_BLOCK_SIZE - 1);
+
+        while address < end {
+            let order = (0..ORDERS)
+                        .rev()
+                        .find(|&order| {
+                            let size = block_size(order);
+                            address % size == 0 && address + size <= end
+                        })
+                        .unwrap();
+
+            self.push(order, address);
+            address += block_size(order);
+            self.heap_size += block_size(order);
+        }
+    }
+
+    /// Returns the number of bytes managed by the allocator.
+    pub fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    /// Allocates a block of memory with the required layout.
+    pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let order = match order(&layout) {
+            Some(order) => order,
+            None        => return ptr::null_mut(),
+        };
+
+        let available = (order..ORDERS).find(|&i| self.free_lists[i].is_some());
+        let mut current = match available {
+            Some(i) => i,
+            None    => return ptr::null_mut(),
+        };
+
+        let address = self.pop(current).unwrap();
+        while current > order {
+            current -= 1;
+            self.push(current, address + block_size(current));
+        }
+
+        address as *mut u8
+    }
+
+    /// Frees the given block of memory and merges it with its free buddies.
+    ///
+    /// block_ptr must be a pointer returned by a call to the alloc function with identical layout.
+    /// Undefined behavior may occur for invalid arguments, thus this function is unsafe.
+    pub unsafe fn dealloc(&mut self, block_ptr: *mut u8, layout: Layout) {
+        if block_ptr == ptr::null_mut() {
+            return;
+        }
+
+        let mut order = order(&layout).unwrap();
+        let mut address = block_ptr as usize;
+
+        while order + 1 < ORDERS {
+            let buddy = address ^ block_size(order);
+            if !self.remove(order, buddy) {
+                break;
+            }
+
+            address = address.min(buddy);
+            order += 1;
+        }
+
+        self.push(order, address);
+    }
+
+    fn push(&mut self, order: usize, address: usize) {
+        assert!(mem::size_of::<FreeBlock>() <= MIN_BLOCK_SIZE);
+
+        let block = address as *mut FreeBlock;
+        unsafe {
+            block.write(FreeBlock {
+                next: self.free_lists[order].take(),
+            });
+        }
+        self.free_lists[order] = NonNull::new(block);
+    }
+
+    fn pop(&mut self, order: usize) -> Option<usize> {
+        let head = self.free_lists[order]?;
+        self.free_lists[order] = unsafe { head.as_ref().next };
+
+        Some(head.as_ptr() as usize)
+    }
+
+    /// Removes the block at `address` from the free list of `order`,
+    /// returning false if it is not free.
+    fn remove(&mut self, order: usize, address: usize) -> bool {
+        let mut link = &mut self.free_lists[order];
+
+        while let Some(block) = *link {
+            if block.as_ptr() as usize == address {
+                *link = unsafe { block.as_ref().next };
+                return true;
+            }
+
+            link = unsafe { &mut (*block.as_ptr()).next };
+        }
+
+        false
+    }
+}
+
+/// The size of the blocks of the given order
+fn block_size(order: usize) -> usize {
+    MIN_BLOCK_SIZE << order
+}
+
+/// The order of the smallest block with the size and the alignment of `layout`
+fn order(layout: &Layout) -> Option<usize> {
+    let size = layout.size()
+                     .max(layout.align())
+                     .max(MIN_BLOCK_SIZE)
+                     .checked_next_power_of_two()?;
+    let order = (size / MIN_BLOCK_SIZE).trailing_zeros() as usize;
+
+    if order < ORDERS {
+        Some(order)
+    } else {
+        None
+    }
+}
+
+/// Aligns `address` upwards to `align`, which must be a power of two.
+fn align_up(address: usize, align: usize) -> usize {
+    (address + align - 1) & !(align - 1)
+}
+
+unsafe impl GlobalAlloc for Locked<BuddyAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, block_ptr: *mut u8, layout: Layout) {
+        self.lock().dealloc(block_ptr, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEAP_SIZE: usize = 1024;
+
+    #[repr(align(1024))]
+    struct AlignedHeap([u8; HEAP_SIZE]);
+
+    #[test_case]
+    fn empty_allocator_always_returns_null() {
+        let mut allocator = BuddyAllocator::empty();
+        let layout = Layout::from_size_align(14, 8).unwrap();
+
+        assert!(allocator.alloc(layout) == ptr::null_mut());
+    }
+
+    #[test_case]
+    fn small_allocations_split_adjacent_blocks() {
+        let mut heap = AlignedHeap([0; HEAP_SIZE]);
+        let heap_start = heap.0.as_mut_ptr();
+        let mut allocator = unsafe {
+            BuddyAllocator::new(heap_start as usize, HEAP_SIZE)
+        };
+        let layout = Layout::from_size_align(10, 1).unwrap();
+
+        let first_block = allocator.alloc(layout);
+        let second_block = allocator.alloc(layout);
+
+        assert_eq!(first_block, heap_start);
+        assert_eq!(second_block as usize, heap_start as usize + MIN_BLOCK_SIZE);
+    }
+
+    #[test_case]
+    fn freed_buddies_are_merged() {
+        let mut heap = AlignedHeap([0; HEAP_SIZE]);
+        let heap_start = heap.0.as_mut_ptr();
+        let mut allocator = unsafe {
+            BuddyAllocator::new(heap_start as usize, HEAP_SIZE)
+        };
+        let small = Layout::from_size_align(16, 1).unwrap();
+        let whole = Layout::from_size_align(HEAP_SIZE, 1).unwrap();
+
+        let first_block = allocator.alloc(small);
+        let second_block = allocator.alloc(small);
+        assert!(allocator.alloc(whole) == ptr::null_mut());
+
+        unsafe {
+            allocator.dealloc(second_block, small);
+            allocator.dealloc(first_block, small);
+        }
+        assert_eq!(allocator.alloc(whole), heap_start);
+    }
+
+    #[test_case]
+    fn alignment_up_to_the_block_size_is_satisfied() {
+        let mut heap = AlignedHeap([0; HEAP_SIZE]);
+        let heap_start = heap.0.as_mut_ptr();
+        let mut allocator = unsafe {
+            BuddyAllocator::new(heap_start as usize, HEAP_SIZE)
+        };
+
+        allocator.alloc(Layout::from_size_align(8, 1).unwrap());
+        for &align in [32, 64, 256].iter() {
+            let block = allocator.alloc(Layout::from_size_align(8, align).unwrap());
+
+            assert!(block != ptr::null_mut());
+            assert_eq!(block as usize % align, 0);
+        }
+    }
+
+    #[test_case]
+    fn unaligned_heap_is_split_into_aligned_blocks() {
+        let mut heap = AlignedHeap([0; HEAP_SIZE]);
+        let heap_start = heap.0.as_mut_ptr() as usize;
+        let allocator = unsafe {
+            BuddyAllocator::new(heap_start + 16, HEAP_SIZE - 16)
+        };
+
+        assert_eq!(allocator.heap_size(), HEAP_SIZE - 16);
+    }
+}
@@ -0,0 +1,186 @@
+use super::Locked;
+use alloc::alloc::{
+    Layout,
+    GlobalAlloc,
+};
+use core::ptr;
+
+/// An allocator which hands out the heap front to back
+///
+/// Memory is only reclaimed once all allocations are freed,
+/// which makes it suitable for short-lived scratch arenas.
+pub struct BumpAllocator {
+    heap_start: usize,
+    heap_end: usize,
+    next: usize,
+    allocations: usize,
+}
+
+impl BumpAllocator {
+    /// Creates an empty allocator. All alloc calls will return null.
+    pub const fn empty() -> Self {
+        BumpAllocator {
+            heap_start: 0,
+            heap_end: 0,
+            next: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Creates a new allocator with the given heap bounds.
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused.
+    pub unsafe fn new(heap_start: usize, heap_size: usize) -> Self {
+        let mut allocator = Self::empty();
+        allocator.init(heap_start, heap_size);
+
+        allocator
+    }
+
+    /// Initialize an empty allocator with the given heap bounds.
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused. This method must be
+    /// called only once and on an empty allocator.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_start = heap_start;
+        self.heap_end = heap_start.saturating_add(heap_size);
+        self.next = heap_start;
+    }
+
+    /// Returns the number of allocations not yet freed.
+    pub fn allocations(&self) -> usize {
+        self.allocations
+    }
+
+    /// Allocates a block of memory with the required layout.
+    pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let block_start = match align_up(self.next, layout.align()) {
+            Some(start) => start,
+            None        => return ptr::null_mut(),
+        };
+        let block_end = match block_start.checked_add(layout.size()) {
+            Some(end) if end <= self.heap_end => end,
+            _                                 => return ptr::null_mut(),
+        };
+
+        self.next = block_end;
+        self.allocations += 1;
+
+        block_start as *mut u8
+    }
+
+    /// Frees the given block of memory.
+    ///
+    /// The memory is reclaimed only when this was the last allocation not yet freed.
+    /// block_ptr must be a pointer returned by a call to the alloc function with identical layout.
+    /// Undefined behavior may occur for invalid arguments, thus this function is unsafe.
+    pub unsafe fn dealloc(&mut self, block_ptr: *mut u8, _layout: Layout) {
+        if block_ptr == ptr::null_mut() {
+            return;
+        }
+
+        self.allocations -= 1;
+        if self.allocations == 0 {
+            self.next = self.heap_start;
+        }
+    }
+}
+
+/// Aligns `address` upwards to `align`, which must be a power of two.
+///
+/// Returns None if the aligned address overflows.
+fn align_up(address: usize, align: usize) -> Option<usize> {
+    address.checked_add(align - 1)
+           .map(|address| address & !(align - 1))
+}
+
+unsafe impl GlobalAlloc for Locked<BumpAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, block_ptr: *mut u8, layout: Layout) {
+        self.lock().dealloc(block_ptr, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn empty_allocator_always_returns_null() {
+        let mut allocator = BumpAllocator::empty();
+        let layout = Layout::from_size_align(14, 8).unwrap();
+
+        assert!(allocator.alloc(layout) == ptr::null_mut());
+    }
+
+    #[test_case]
+    fn alloc_with_unfittable_layout_returns_null() {
+        let mut buffer = [0; 256];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let mut allocator = unsafe {
+            BumpAllocator::new(heap_start as usize, buffer.len())
+        };
+        let layout = Layout::from_size_align(2 * buffer.len(), 8).unwrap();
+
+        assert!(allocator.alloc(layout) == ptr::null_mut());
+    }
+
+    #[test_case]
+    fn different_allocations_return_different_blocks() {
+        let mut buffer = [0; 256];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let mut allocator = unsafe {
+            BumpAllocator::new(heap_start as usize, buffer.len())
+        };
+        let layout = Layout::from_size_align(4, 8).unwrap();
+
+        let first_block  = allocator.alloc(layout);
+        let second_block = allocator.alloc(layout);
+
+        assert!(first_block != ptr::null_mut());
+        assert!(second_block != ptr::null_mut());
+        assert!(first_block != second_block);
+        assert_eq!(first_block as usize % 8, 0);
+        assert_eq!(second_block as usize % 8, 0);
+    }
+
+    #[test_case]
+    fn memory_is_reclaimed_once_all_allocations_are_freed() {
+        let mut buffer = [0; 256];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let mut allocator = unsafe {
+            BumpAllocator::new(heap_start as usize, buffer.len())
+        };
+        let layout = Layout::from_size_align(100, 1).unwrap();
+
+        let first_block = allocator.alloc(layout);
+        let second_block = allocator.alloc(layout);
+        assert!(allocator.alloc(layout) == ptr::null_mut());
+
+        unsafe {
+            allocator.dealloc(first_block, layout);
+        }
+        assert!(allocator.alloc(layout) == ptr::null_mut());
+
+        unsafe {
+            allocator.dealloc(second_block, layout);
+        }
+        assert_eq!(allocator.allocations(), 0);
+        assert_eq!(allocator.alloc(layout), first_block);
+    }
+
+    #[test_case]
+    fn alloc_near_the_end_of_the_address_space_returns_null() {
+        let mut allocator = unsafe {
+            BumpAllocator::new(usize::MAX - 16, 16)
+        };
+        let layout = Layout::from_size_align(32, 64).unwrap();
+
+        assert!(allocator.alloc(layout) == ptr::null_mut());
+    }
+}
@@ -1,6 +1,8 @@
 use super::{
     Locked,
 };
+use crate::memory;
+use x86_64::VirtAddr;
 use alloc::alloc::{
     Layout,
     GlobalAlloc,
@@ -17,14 +19,33 @@ struct Node {
     next: Option<&'static mut Node>,
 }
 
+/// A block held from the fallback allocator while looking for a free region of the heap.
+/// It links the block held before it, so that all of them can be given back afterwards.
+struct Probe {
+    size: usize,
+    next: *mut Probe,
+}
+
+/// The smallest region the fallback allocator can keep track of as free
+const MIN_FALLBACK_BLOCK_SIZE: usize = 2 * mem::size_of::<usize>();
+
 /// Represents the layout of a fixed-size block.
-#[derive(Copy, Clone, Debug)]
-struct BlockLayout {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockLayout {
     size: usize,
     align: usize,
 }
 
-/// A block layout table with an entry for the block class of each free list.
+impl BlockLayout {
+    pub const fn new(size: usize, align: usize) -> Self {
+        BlockLayout {
+            size,
+            align,
+        }
+    }
+}
+
+/// The default block layout table with an entry for the block class of each free list.
 const BLOCK_LAYOUTS: &[BlockLayout] = &[
     BlockLayout{ size: 8, align: 8 },
     BlockLayout{ size: 16, align: 16 },
@@ -37,20 +58,110 @@ const BLOCK_LAYOUTS: &[BlockLayout] = &[
     BlockLayout{ size: 2048, align: 2048 },
 ];
 
-/// The number of the free lists used by the allocator.
-const FREE_LISTS_COUNT: usize = BLOCK_LAYOUTS.len();
+/// The maximum number of block classes, thus free lists, an allocator can have.
+pub const MAX_BLOCK_CLASSES: usize = 16;
+
+/// The reasons block layouts cannot be the block classes of an allocator
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockLayoutsError {
+    /// There are more than `MAX_BLOCK_CLASSES` layouts
+    TooMany,
+    /// The sizes of the layouts are not strictly ascending
+    NotAscending,
+    /// A layout is too small or not aligned enough for a free list node,
+    /// or its alignment is not a power of two
+    CannotHoldNode,
+}
+
+/// Allocation counters of a single block class
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockClassStats {
+    /// The size of the blocks in the class
+    pub block_size: usize,
+    /// The allocations served by reusing a block from the free list
+    pub free_list_allocs: usize,
+    /// The allocations served by the fallback allocator as the free list was empty
+    pub fallback_allocs: usize,
+    /// The blocks handed out and not yet freed
+    pub outstanding: usize,
+}
+
+impl BlockClassStats {
+    const fn new() -> Self {
+        BlockClassStats {
+            block_size: 0,
+            free_list_allocs: 0,
+            fallback_allocs: 0,
+            outstanding: 0,
+        }
+    }
+}
+
+/// Allocation counters of a `FixedSizeBlockAllocator`
+#[derive(Copy, Clone, Debug)]
+pub struct AllocatorStats {
+    /// The counters of each block class, of which the first `class_count` are used
+    class_stats: [BlockClassStats; MAX_BLOCK_CLASSES],
+    class_count: usize,
+    /// The allocations too large for any block class
+    pub large_allocs: usize,
+    /// The allocations too large for any block class, not yet freed
+    pub large_outstanding: usize,
+    /// The large allocations served with pages of their own instead of the heap
+    pub page_allocs: usize,
+    /// The allocations served with pages of their own, not yet freed.
+    /// Those whose pages could not be unmapped when freed are leaked and still counted.
+    pub page_outstanding: usize,
+    /// The sum of the sizes of all successful allocation requests
+    pub bytes_requested: usize,
+}
+
+impl AllocatorStats {
+    const fn new() -> Self {
+        AllocatorStats {
+            class_stats: [BlockClassStats::new(); MAX_BLOCK_CLASSES],
+            class_count: 0,
+            large_allocs: 0,
+            large_outstanding: 0,
+            page_allocs: 0,
+            page_outstanding: 0,
+            bytes_requested: 0,
+        }
+    }
+
+    /// The counters of each block class of the allocator, in increasing block size
+    pub fn classes(&self) -> &[BlockClassStats] {
+        &self.class_stats[..self.class_count]
+    }
+}
 
 pub struct FixedSizeBlockAllocator {
-    free_list_heads: [Option<&'static mut Node>; FREE_LISTS_COUNT],
+    /// The block class of each free list, in increasing block size
+    layouts: &'static [BlockLayout],
+    free_list_heads: [Option<&'static mut Node>; MAX_BLOCK_CLASSES],
     fallback_allocator: linked_list_allocator::Heap,
+    initial_heap_size: usize,
+    used_bytes: usize,
+    stats: AllocatorStats,
+    /// The start of the region at the top of the heap held from the fallback allocator
+    /// after the heap was shrunk, so that it is never handed out
+    claimed_start: Option<usize>,
+    /// The bytes at the top of the claimed region which are no longer part of the heap
+    released_bytes: usize,
 }
 
 impl FixedSizeBlockAllocator {
     /// Creates an empty allocator. All alloc calls will return null.
     pub const fn empty() -> Self {
         FixedSizeBlockAllocator {
-            free_list_heads: [None; FREE_LISTS_COUNT],
+            layouts: BLOCK_LAYOUTS,
+            free_list_heads: [None; MAX_BLOCK_CLASSES],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            initial_heap_size: 0,
+            used_bytes: 0,
+            stats: AllocatorStats::new(),
+            claimed_start: None,
+            released_bytes: 0,
         }
     }
 
@@ -59,10 +170,29 @@ impl FixedSizeBlockAllocator {
     /// This function is unsafe because the caller must guarantee that the given
     /// heap bounds are valid and that the heap is unused.
     pub unsafe fn new(heap_start: usize, heap_size: usize) -> Self {
+        Self::with_layouts(heap_start, heap_size, BLOCK_LAYOUTS).expect("The default block layouts are invalid")
+    }
+
+    /// Creates a new allocator with the given heap bounds and a free list for each of `layouts`.
+    ///
+    /// The free lists are stored inline, so there can be at most `MAX_BLOCK_CLASSES` layouts.
+    /// Fails if there are more, if their sizes are not strictly ascending
+    /// or if any of them cannot hold a free list node.
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused.
+    pub unsafe fn with_layouts(
+        heap_start: usize,
+        heap_size: usize,
+        layouts: &'static [BlockLayout],
+    ) -> Result<Self, BlockLayoutsError> {
+        check_layouts(layouts)?;
+
         let mut allocator = Self::empty();
+        allocator.layouts = layouts;
         allocator.init(heap_start, heap_size);
 
-        allocator
+        Ok(allocator)
     }
 
     /// Initialize an empty allocator with the given heap bounds.
@@ -72,40 +202,178 @@ impl FixedSizeBlockAllocator {
     /// called only once and on an empty allocator.
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
         self.fallback_allocator.init(heap_start, heap_size);
+        self.initial_heap_size = heap_size;
+    }
+
+    /// Extends the heap by `by` bytes.
+    ///
+    /// Memory released by `shrink` is taken back first.
+    /// `by` is expected to be a multiple of the sizes the heap was shrunk by.
+    ///
+    /// This function is unsafe because the caller must guarantee that the memory
+    /// right after the end of the heap is valid and unused for `by` bytes.
+    pub unsafe fn extend(&mut self, by: usize) {
+        if let Some(claimed_start) = self.claimed_start.take() {
+            let reused = by.min(self.released_bytes);
+            let freed_end = self.heap_end() + reused;
+
+            self.fallback_dealloc(claimed_start, freed_end - claimed_start);
+            self.released_bytes -= reused;
+            if self.released_bytes > 0 {
+                self.claimed_start = Some(freed_end);
+            }
+
+            if by > reused {
+                self.fallback_allocator.extend(by - reused);
+            }
+        }
+        else {
+            self.fallback_allocator.extend(by);
+        }
+    }
+
+    /// Shrinks the heap by its top `by` bytes if no block is allocated in them.
+    ///
+    /// Blocks from the free lists which lie in the region are given back to the fallback
+    /// allocator, which then holds the region so that it is never handed out again.
+    /// Returns whether the heap was shrunk. `by` is expected to be a multiple of the page size.
+    ///
+    /// Once shrunk, the caller is free to unmap the region.
+    pub fn shrink(&mut self, by: usize) -> bool {
+        if by == 0 {
+            return true;
+        }
+        if by >= self.heap_size() {
+            return false;
+        }
+
+        let new_end = self.heap_end() - by;
+        let free_end = self.claimed_start.unwrap_or_else(|| self.fallback_allocator.top());
+
+        self.release_free_blocks_above(new_end);
+        match self.claim_free_region(new_end, free_end) {
+            Some(start) => {
+                let start = if new_end - start >= MIN_FALLBACK_BLOCK_SIZE {
+                    unsafe {
+                        self.fallback_dealloc(start, new_end - start);
+                    }
+                    new_end
+                }
+                else {
+                    start
+                };
+
+                self.claimed_start = Some(start);
+                self.released_bytes += by;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns the end address of the heap.
+    pub fn heap_end(&self) -> usize {
+        self.fallback_allocator.top() - self.released_bytes
+    }
+
+    /// Returns the size of the heap in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.fallback_allocator.size() - self.released_bytes
+    }
+
+    /// Returns the number of bytes in the blocks handed out and not yet freed.
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    /// Returns the allocation counters
+    pub fn stats(&self) -> AllocatorStats {
+        let mut stats = self.stats;
+        stats.class_count = self.layouts.len();
+        for (class, block_layout) in stats.class_stats.iter_mut().zip(self.layouts) {
+            class.block_size = block_layout.size;
+        }
+
+        stats
     }
 
     /// Allocates a block of memory with the required layout.
     pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.free_list_index(&layout) {
+        let index = self.free_list_index(&layout);
+        let block_ptr = match index {
             Some(i) => self.free_list_alloc(i),
             None    => self.fallback_alloc(layout),
+        };
+
+        if block_ptr != ptr::null_mut() {
+            self.used_bytes += self.block_size(&layout);
+            self.stats.bytes_requested += layout.size();
+            match index {
+                Some(i) => self.stats.class_stats[i].outstanding += 1,
+                None    => {
+                    self.stats.large_allocs += 1;
+                    self.stats.large_outstanding += 1;
+                },
+            }
         }
+
+        block_ptr
     }
 
     /// Allocates a block using the corresponding free list
     /// or the fallback allocator in case that list is empty.
     ///
-    /// Panics if index >= FREE_LISTS_COUNT
+    /// Panics if index is not the index of a block class
     fn free_list_alloc(&mut self, index: usize) -> *mut u8 {
-        assert!(index < FREE_LISTS_COUNT);
+        assert!(index < self.layouts.len());
 
         match self.free_list_heads[index].take() {
             Some(node) => {
                 self.free_list_heads[index] = node.next.take();
+                self.stats.class_stats[index].free_list_allocs += 1;
 
                 node as *mut Node 
                      as *mut u8
             },
             None => {
-                let block_layout = &BLOCK_LAYOUTS[index]; 
+                let block_layout = &self.layouts[index];
                 let layout = Layout::from_size_align(block_layout.size, block_layout.align)
                              .unwrap();
 
-                self.fallback_alloc(layout)
+                let block_ptr = self.fallback_alloc(layout);
+                if block_ptr != ptr::null_mut() {
+                    self.stats.class_stats[index].fallback_allocs += 1;
+                }
+
+                block_ptr
             }
         }
     }
 
+    /// Maps `pages` fresh pages for an allocation, bypassing the heap.
+    ///
+    /// Returns null if they cannot be mapped.
+    fn page_alloc(&mut self, pages: usize) -> *mut u8 {
+        match memory::map_large_allocation(pages) {
+            Some(start) => {
+                self.stats.page_allocs += 1;
+                self.stats.page_outstanding += 1;
+
+                start.as_mut_ptr()
+            },
+            None => ptr::null_mut(),
+        }
+    }
+
+    /// Unmaps the `pages` pages of an allocation served by `page_alloc`.
+    ///
+    /// The allocation is counted as freed only if its pages were unmapped.
+    unsafe fn page_dealloc(&mut self, block_ptr: *mut u8, pages: usize) {
+        if memory::unmap_large_allocation(VirtAddr::from_ptr(block_ptr), pages) {
+            self.stats.page_outstanding -= 1;
+        }
+    }
+
      /// Allocates a block using the fallback allocator.
      fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
         self.fallback_allocator
@@ -114,6 +382,110 @@ impl FixedSizeBlockAllocator {
             .unwrap_or(ptr::null_mut())
     }
 
+    /// Gives the `size` bytes at `start` back to the fallback allocator.
+    ///
+    /// The region must be held from the fallback allocator and must not be in use.
+    unsafe fn fallback_dealloc(&mut self, start: usize, size: usize) {
+        let layout = Layout::from_size_align_unchecked(size, mem::align_of::<Probe>());
+
+        self.fallback_allocator.deallocate(NonNull::new_unchecked(start as *mut u8), layout);
+    }
+
+    /// Gives the blocks from the free lists which end above `limit` back to the fallback allocator.
+    fn release_free_blocks_above(&mut self, limit: usize) {
+        for (index, block_layout) in self.layouts.iter().enumerate() {
+            let layout = Layout::from_size_align(block_layout.size, block_layout.align).unwrap();
+            let mut list = self.free_list_heads[index].take();
+
+            while let Some(node) = list {
+                list = node.next.take();
+
+                let block_start = node as *mut Node as usize;
+                if block_start + block_layout.size > limit {
+                    unsafe {
+                        let block_ptr = NonNull::new_unchecked(block_start as *mut u8);
+                        self.fallback_allocator.deallocate(block_ptr, layout);
+                    }
+                }
+                else {
+                    node.next = self.free_list_heads[index].take();
+                    self.free_list_heads[index] = Some(node);
+                }
+            }
+        }
+    }
+
+    /// Makes the fallback allocator hand out the free region from `start` to `end`,
+    /// possibly starting a few bytes lower. Returns the start of the region it handed out,
+    /// or None if a part of the region is allocated.
+    ///
+    /// There is nothing above `end` the fallback allocator can hand out.
+    /// As it always hands out the lowest fitting region, the lower free regions which fit
+    /// are held while looking for this one and are given back afterwards.
+    fn claim_free_region(&mut self, start: usize, end: usize) -> Option<usize> {
+        let mut held: *mut Probe = ptr::null_mut();
+
+        let claimed = loop {
+            let size = end - start;
+            let address = match self.probe(size) {
+                Some(address) => address,
+                None          => break None,
+            };
+
+            if address >= start {
+                if address == start {
+                    break Some(start);
+                }
+
+                unsafe {
+                    self.fallback_dealloc(address, size);
+                }
+                break None;
+            }
+            else if address + size <= start {
+                held = unsafe { hold(held, address, size) };
+                continue;
+            }
+
+            // The free region at `address` covers `start` and is claimed as a whole
+            // if it reaches `end`, otherwise some of the region is allocated
+            unsafe {
+                self.fallback_dealloc(address, size);
+            }
+            let region_start = address;
+            let size = end - region_start;
+
+            break loop {
+                match self.probe(size) {
+                    Some(address) if address == region_start => break Some(region_start),
+                    Some(address) => held = unsafe { hold(held, address, size) },
+                    None          => break None,
+                }
+            };
+        };
+
+        while !held.is_null() {
+            unsafe {
+                let Probe { size, next } = held.read();
+                self.fallback_dealloc(held as usize, size);
+                held = next;
+            }
+        }
+
+        claimed
+    }
+
+    /// Returns the address of the lowest free region of `size` bytes of the fallback allocator
+    /// after handing it out.
+    fn probe(&mut self, size: usize) -> Option<usize> {
+        let layout = Layout::from_size_align(size, mem::align_of::<Probe>()).unwrap();
+
+        self.fallback_allocator
+            .allocate_first_fit(layout)
+            .map(|ptr| ptr.as_ptr() as usize)
+            .ok()
+    }
+
     /// Frees the given block of memory.
     ///
     /// block_ptr must be a pointer returned by a call to the alloc function with identical layout.
@@ -123,10 +495,14 @@ impl FixedSizeBlockAllocator {
             return;
         }
 
+        self.used_bytes -= self.block_size(&layout);
+
         match self.free_list_index(&layout) {
             Some(index) => {
-                assert!(mem::size_of::<Node>() <= BLOCK_LAYOUTS[index].size);
-                assert!(mem::align_of::<Node>() <= BLOCK_LAYOUTS[index].align);
+                self.stats.class_stats[index].outstanding -= 1;
+
+                assert!(mem::size_of::<Node>() <= self.layouts[index].size);
+                assert!(mem::align_of::<Node>() <= self.layouts[index].align);
 
                 let old_head = self.free_list_heads[index].take();
                 let new_head = block_ptr as *mut Node;
@@ -137,17 +513,40 @@ impl FixedSizeBlockAllocator {
                 self.free_list_heads[index] = Some(&mut *new_head);
             }
             None => {
+                self.stats.large_outstanding -= 1;
+
                 let block_ptr = NonNull::new(block_ptr).unwrap();
                 self.fallback_allocator.deallocate(block_ptr, layout);
             }
         }
     }
 
+    /// Checks if a block allocated with `layout` can also serve `new_layout`,
+    /// which is the case when both map to the same block class.
+    pub fn fits_in_place(&self, layout: &Layout, new_layout: &Layout) -> bool {
+        match (self.free_list_index(layout), self.free_list_index(new_layout)) {
+            (Some(i), Some(j)) => i == j,
+            _                  => false,
+        }
+    }
+
+    /// The size of the block an allocation with the given layout is served with.
+    fn block_size(&self, layout: &Layout) -> usize {
+        match self.free_list_index(layout) {
+            Some(i) => self.layouts[i].size,
+            None    => layout.size(),
+        }
+    }
+
     /// Choose an appropriate free list for the given layout.
+    ///
+    /// Block classes which are not smaller than the heap the allocator was initialized with
+    /// are never used. The initial size is used instead of the current one,
+    /// so that the class of a layout does not change as the heap grows.
     fn free_list_index(&self, layout: &Layout) -> Option<usize> {
-        let heap_size = self.fallback_allocator.size(); 
+        let heap_size = self.initial_heap_size;
 
-        BLOCK_LAYOUTS
+        self.layouts
         .iter()
         .filter(|block| {
             block.size < heap_size
@@ -159,19 +558,111 @@ impl FixedSizeBlockAllocator {
     }
 }
 
+/// Links the held block of `size` bytes at `address` to the previously held ones
+/// and returns a pointer to it.
+unsafe fn hold(held: *mut Probe, address: usize, size: usize) -> *mut Probe {
+    let probe = address as *mut Probe;
+    probe.write(Probe {
+        size,
+        next: held,
+    });
+
+    probe
+}
+
+/// Checks that the layouts fit in the free lists of an allocator, that their sizes are strictly
+/// ascending and that each of them is a power-of-two aligned block which can hold a `Node`.
+fn check_layouts(layouts: &[BlockLayout]) -> Result<(), BlockLayoutsError> {
+    let can_hold_node = |block: &BlockLayout| {
+        block.size  >= mem::size_of::<Node>() &&
+        block.align >= mem::align_of::<Node>() &&
+        block.align.is_power_of_two()
+    };
+
+    if layouts.len() > MAX_BLOCK_CLASSES {
+        Err(BlockLayoutsError::TooMany)
+    } else if !layouts.windows(2).all(|pair| pair[0].size < pair[1].size) {
+        Err(BlockLayoutsError::NotAscending)
+    } else if !layouts.iter().all(can_hold_node) {
+        Err(BlockLayoutsError::CannotHoldNode)
+    } else {
+        Ok(())
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    /// Allocates a block of memory with the required layout.
+    ///
+    /// If the allocator manages the Kernel Heap, large allocations of whole pages are
+    /// served with freshly mapped pages of their own, falling back to the Heap.
+    /// If the Heap is exhausted, it is grown once and the allocation is retried.
+    /// If the allocation still fails, the out-of-memory handler is called
+    /// with the allocator unlocked before returning null.
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.lock().alloc(layout)
+        let block_ptr = {
+            let mut allocator = self.lock();
+            let manages_kernel_heap = allocator.heap_end() == memory::heap_end();
+
+            let page_ptr = match super::large_allocation_pages(&layout) {
+                Some(pages) if manages_kernel_heap => allocator.page_alloc(pages),
+                _                                  => ptr::null_mut(),
+            };
+            let block_ptr = if page_ptr != ptr::null_mut() { page_ptr } else { allocator.alloc(layout) };
+
+            if block_ptr == ptr::null_mut()
+               && manages_kernel_heap
+               && super::grow(&mut allocator, super::growth_pages(&layout)).is_ok() {
+                allocator.alloc(layout)
+            }
+            else {
+                block_ptr
+            }
+        };
+
+        if block_ptr == ptr::null_mut() {
+            super::out_of_memory(layout);
+        }
+
+        block_ptr
     }
 
+    /// Frees the block, unmapping it if it was served with pages of its own.
     unsafe fn dealloc(&self, block_ptr: *mut u8, layout: Layout) {
-        self.lock().dealloc(block_ptr, layout)
+        let mut allocator = self.lock();
+
+        match super::large_allocation_pages(&layout) {
+            Some(pages) if memory::is_large_allocation(VirtAddr::from_ptr(block_ptr)) => {
+                allocator.page_dealloc(block_ptr, pages)
+            },
+            _ => allocator.dealloc(block_ptr, layout),
+        }
+    }
+
+    /// Resizes the block in place if the new size maps to the same block class.
+    ///
+    /// Otherwise a new block is allocated, the first `min(old size, new_size)` bytes
+    /// are copied to it and the old block is freed.
+    unsafe fn realloc(&self, block_ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+
+        if self.lock().fits_in_place(&layout, &new_layout) {
+            return block_ptr;
+        }
+
+        let new_block_ptr = self.alloc(new_layout);
+        if new_block_ptr != ptr::null_mut() {
+            ptr::copy_nonoverlapping(block_ptr, new_block_ptr, layout.size().min(new_size));
+            self.dealloc(block_ptr, layout);
+        }
+
+        new_block_ptr
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec::Vec;
 
     #[test_case]
     fn empty_allocator_always_returns_null() {
@@ -239,4 +730,244 @@ mod tests {
         }
         assert!(allocator.alloc(layout) != ptr::null_mut());
     }
-}
\ No newline at end of file
+
+    #[test_case]
+    fn stats_count_the_allocations_of_each_class() {
+        let mut buffer = [0; 4096];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let mut allocator = unsafe {
+            FixedSizeBlockAllocator::new(heap_start as usize, buffer.len())
+        };
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let large = Layout::from_size_align(3000, 8).unwrap();
+
+        let blocks = [allocator.alloc(small), allocator.alloc(small), allocator.alloc(small)];
+        unsafe {
+            allocator.dealloc(blocks[0], small);
+        }
+        allocator.alloc(small);
+        allocator.alloc(large);
+
+        let stats = allocator.stats();
+        assert_eq!(
+            stats.classes()[0],
+            BlockClassStats {
+                block_size: 8,
+                free_list_allocs: 1,
+                fallback_allocs: 3,
+                outstanding: 3,
+            }
+        );
+        assert!(stats.classes()[1..].iter().all(|class| class.outstanding == 0));
+        assert_eq!(stats.large_allocs, 1);
+        assert_eq!(stats.large_outstanding, 1);
+        assert_eq!(stats.bytes_requested, 4 * 8 + 3000);
+    }
+
+    #[test_case]
+    fn allocations_are_routed_to_the_custom_block_classes() {
+        static LAYOUTS: [BlockLayout; 3] = [
+            BlockLayout::new(32, 8),
+            BlockLayout::new(96, 8),
+            BlockLayout::new(384, 8),
+        ];
+        let mut buffer = [0; 4096];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let mut allocator = unsafe {
+            FixedSizeBlockAllocator::with_layouts(heap_start as usize, buffer.len(), &LAYOUTS)
+        }
+        .expect("Invalid block layouts");
+
+        allocator.alloc(Layout::from_size_align(80, 8).unwrap());
+        allocator.alloc(Layout::from_size_align(300, 8).unwrap());
+        allocator.alloc(Layout::from_size_align(500, 8).unwrap());
+
+        let stats = allocator.stats();
+        assert_eq!(stats.classes().len(), LAYOUTS.len());
+        assert_eq!(stats.classes()[1].block_size, 96);
+        assert_eq!(stats.classes()[1].outstanding, 1);
+        assert_eq!(stats.classes()[2].block_size, 384);
+        assert_eq!(stats.classes()[2].outstanding, 1);
+        assert_eq!(stats.large_allocs, 1);
+        assert_eq!(allocator.used_bytes(), 96 + 384 + 500);
+    }
+
+    #[test_case]
+    fn block_layouts_are_validated() {
+        assert_eq!(check_layouts(BLOCK_LAYOUTS), Ok(()));
+        assert_eq!(check_layouts(&[BlockLayout::new(96, 8), BlockLayout::new(384, 8)]), Ok(()));
+        assert_eq!(
+            check_layouts(&[BlockLayout::new(384, 8), BlockLayout::new(96, 8)]),
+            Err(BlockLayoutsError::NotAscending)
+        );
+        assert_eq!(check_layouts(&[BlockLayout::new(4, 4)]), Err(BlockLayoutsError::CannotHoldNode));
+        assert_eq!(check_layouts(&[BlockLayout::new(96, 24)]), Err(BlockLayoutsError::CannotHoldNode));
+
+        let too_many: Vec<BlockLayout> = (0..=MAX_BLOCK_CLASSES).map(|i| BlockLayout::new(8 << i, 8))
+                                                                 .collect();
+        assert_eq!(check_layouts(&too_many), Err(BlockLayoutsError::TooMany));
+    }
+
+    #[test_case]
+    fn allocator_with_too_many_block_layouts_is_rejected() {
+        static TOO_MANY: [BlockLayout; MAX_BLOCK_CLASSES + 1] = [BlockLayout::new(8, 8); MAX_BLOCK_CLASSES + 1];
+        let mut buffer = [0; 64];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+
+        let result = unsafe {
+            FixedSizeBlockAllocator::with_layouts(heap_start as usize, buffer.len(), &TOO_MANY)
+        };
+        assert!(matches!(result, Err(BlockLayoutsError::TooMany)));
+    }
+
+    #[test_case]
+    fn block_class_does_not_change_as_the_heap_fills_or_grows() {
+        let mut buffer = [0; 4096];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let mut allocator = unsafe {
+            FixedSizeBlockAllocator::new(heap_start as usize, buffer.len() / 2)
+        };
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let largest_class = Layout::from_size_align(2048, 8).unwrap();
+
+        assert!(allocator.alloc(Layout::from_size_align(1500, 1).unwrap()) != ptr::null_mut());
+        assert!(allocator.alloc(small) != ptr::null_mut());
+        assert_eq!(allocator.stats().classes()[0].outstanding, 1);
+
+        unsafe {
+            allocator.extend(buffer.len() / 2);
+        }
+        assert!(allocator.alloc(largest_class) != ptr::null_mut());
+        assert_eq!(allocator.stats().large_allocs, 2);
+    }
+
+    #[test_case]
+    fn heap_shrinks_only_when_its_top_is_free() {
+        // the regions handed out by the fallback allocator must be aligned like a `Probe`
+        let mut buffer = [0u64; 512];
+        let heap_size = 8 * buffer.len();
+        let heap_start = buffer.as_mut_ptr() as usize;
+        let mut allocator = unsafe {
+            FixedSizeBlockAllocator::new(heap_start, heap_size)
+        };
+        let heap_end = allocator.heap_end();
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let large = Layout::from_size_align(3580, 8).unwrap();
+
+        // the last small block ends up in the top 512 bytes
+        let blocks = [allocator.alloc(small), allocator.alloc(large), allocator.alloc(small)];
+        assert!(!allocator.shrink(512));
+
+        unsafe {
+            allocator.dealloc(blocks[1], large);
+            allocator.dealloc(blocks[2], small);
+        }
+        assert!(allocator.shrink(512));
+        assert_eq!(allocator.heap_end(), heap_end - 512);
+        assert_eq!(allocator.heap_size(), heap_size - 512);
+        assert!(allocator.alloc(large) == ptr::null_mut());
+
+        unsafe {
+            allocator.extend(512);
+        }
+        assert_eq!(allocator.heap_end(), heap_end);
+        assert!(allocator.alloc(large) != ptr::null_mut());
+    }
+
+    #[test_case]
+    fn realloc_within_a_class_keeps_the_block() {
+        let mut buffer = [0; 4096];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let allocator = Locked::new(unsafe {
+            FixedSizeBlockAllocator::new(heap_start as usize, buffer.len())
+        });
+        let layout = Layout::from_size_align(20, 8).unwrap();
+
+        unsafe {
+            let block = allocator.alloc(layout);
+            assert!(block != ptr::null_mut());
+
+            assert_eq!(allocator.realloc(block, layout, 30), block);
+        }
+    }
+
+    #[test_case]
+    fn realloc_to_another_class_copies_the_contents() {
+        let mut buffer = [0; 4096];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let allocator = Locked::new(unsafe {
+            FixedSizeBlockAllocator::new(heap_start as usize, buffer.len())
+        });
+        let layout = Layout::from_size_align(16, 8).unwrap();
+
+        unsafe {
+            let block = allocator.alloc(layout);
+            assert!(block != ptr::null_mut());
+            block.write_bytes(0xab, layout.size());
+
+            let new_block = allocator.realloc(block, layout, 100);
+            assert!(new_block != ptr::null_mut());
+            assert!(new_block != block);
+
+            let contents = core::slice::from_raw_parts(new_block, layout.size());
+            assert!(contents.iter().all(|&b| b == 0xab));
+        }
+    }
+
+    #[test_case]
+    fn large_allocations_get_pages_of_their_own() {
+        use crate::memory::PAGE_SIZE;
+
+        let before = super::super::stats();
+        let buffer: Vec<u8> = Vec::with_capacity(super::PAGE_ALLOCATION_MIN_SIZE);
+        let during = super::super::stats();
+        let address = buffer.as_ptr() as usize;
+        drop(buffer);
+
+        assert_eq!(address % PAGE_SIZE, 0);
+        assert!(memory::is_large_allocation(VirtAddr::new(address as u64)));
+        assert_eq!(during.page_allocs, before.page_allocs + 1);
+        assert_eq!(super::super::stats().page_outstanding, before.page_outstanding);
+        assert!(memory::translate_addr(VirtAddr::new(address as u64)).is_none());
+    }
+
+    #[test_case]
+    fn large_allocation_freed_with_the_kernel_memory_in_use_stays_outstanding() {
+        let buffer: Vec<u8> = Vec::with_capacity(super::PAGE_ALLOCATION_MIN_SIZE);
+        let address = VirtAddr::new(buffer.as_ptr() as u64);
+        let before = super::super::stats();
+
+        memory::with_kernel_memory(|_, _| drop(buffer));
+
+        assert_eq!(super::super::stats().page_outstanding, before.page_outstanding);
+        assert!(memory::translate_addr(address).is_some());
+    }
+
+    #[test_case]
+    fn failed_allocation_calls_the_oom_handler() {
+        use core::sync::atomic::{
+            AtomicBool,
+            Ordering,
+        };
+
+        static CALLED: AtomicBool = AtomicBool::new(false);
+
+        fn set_called(_: Layout) {
+            CALLED.store(true, Ordering::Relaxed);
+        }
+
+        let mut buffer = [0; 256];
+        let heap_start: *mut u8 = buffer.as_mut_ptr();
+        let allocator = Locked::new(unsafe {
+            FixedSizeBlockAllocator::new(heap_start as usize, buffer.len())
+        });
+        let layout = Layout::from_size_align(2 * buffer.len(), 8).unwrap();
+
+        let previous = super::super::set_oom_handler(set_called);
+        let block = unsafe { allocator.alloc(layout) };
+        super::super::set_oom_handler(previous);
+
+        assert!(block == ptr::null_mut());
+        assert!(CALLED.load(Ordering::Relaxed));
+    }
+}
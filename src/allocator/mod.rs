@@ -1,9 +1,34 @@
+pub mod buddy;
+pub mod bump;
 pub mod fixed_size_block;
 
+pub use buddy::BuddyAllocator;
+pub use bump::BumpAllocator;
 use fixed_size_block::FixedSizeBlockAllocator;
+pub use fixed_size_block::{
+    AllocatorStats,
+    BlockClassStats,
+};
+use alloc::alloc::Layout;
+use crate::serial_println;
+use crate::memory::{
+    self,
+    HeapGrowthError,
+    PAGE_SIZE,
+};
+
+/// The minimum number of pages the Heap grows by when it is exhausted
+const MIN_HEAP_GROWTH_PAGES: usize = 16;
+
+/// A function called with the layout of an allocation which failed
+///
+/// It is called from within the global allocator, so it must not allocate.
+pub type OomHandler = fn(Layout);
+
+static OOM_HANDLER: spin::Mutex<OomHandler> = spin::Mutex::new(report_oom);
 
 #[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::empty());
+static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::with_label(FixedSizeBlockAllocator::empty(), "global allocator");
 
 /// Initializes the global allocator with the given mapped Heap region.
 /// 
@@ -15,19 +40,133 @@ pub unsafe fn init_heap(heap_start: usize, heap_size: usize) {
     ALLOCATOR.lock().init(heap_start, heap_size);
 }
 
+/// Returns the size of the Heap and the number of bytes used in it.
+pub fn heap_usage() -> (usize, usize) {
+    let allocator = ALLOCATOR.lock();
+
+    (allocator.heap_size(), allocator.used_bytes())
+}
+
+/// Returns the allocation counters of the global allocator.
+pub fn stats() -> AllocatorStats {
+    ALLOCATOR.lock().stats()
+}
+
+/// Grows the Heap by `additional_pages` pages.
+pub fn grow_heap(additional_pages: usize) -> Result<(), HeapGrowthError> {
+    grow(&mut ALLOCATOR.lock(), additional_pages)
+}
+
+/// Shrinks the Heap by its top `bytes` bytes if the global allocator has no block in them.
+///
+/// Returns whether the Heap was shrunk. It is up to the caller to unmap the region afterwards.
+pub(crate) fn release_heap_top(bytes: usize) -> bool {
+    ALLOCATOR.lock().shrink(bytes)
+}
+
+/// Takes back the top `bytes` bytes of the Heap given up by `release_heap_top`.
+///
+/// ## Safety
+///
+/// The caller must guarantee that the region is still mapped and is not used anywhere else.
+pub(crate) unsafe fn reclaim_heap_top(bytes: usize) {
+    ALLOCATOR.lock().extend(bytes);
+}
+
+/// Sets the function called when an allocation fails, returning the previous one.
+///
+/// By default the failing layout and the memory usage are reported on serial.
+pub fn set_oom_handler(handler: OomHandler) -> OomHandler {
+    core::mem::replace(&mut *OOM_HANDLER.lock(), handler)
+}
+
+/// Calls the out-of-memory handler for the failed allocation with `layout`.
+fn out_of_memory(layout: Layout) {
+    let handler = *OOM_HANDLER.lock();
+
+    handler(layout);
+}
+
+fn report_oom(layout: Layout) {
+    serial_println!("Out of memory: allocating {:?} failed", layout);
+    serial_println!("{}", memory::stats());
+}
+
+/// Maps more pages after the end of the Heap and extends `allocator` with them.
+fn grow(allocator: &mut FixedSizeBlockAllocator, additional_pages: usize) -> Result<(), HeapGrowthError> {
+    let growth = memory::grow_heap(additional_pages)?;
+    unsafe {
+        allocator.extend(growth);
+    }
+
+    Ok(())
+}
+
+/// The smallest allocation served with pages of its own instead of the Heap
+///
+/// Smaller ones, even of whole pages, are cheaper to serve from the Heap and are counted in its usage.
+const PAGE_ALLOCATION_MIN_SIZE: usize = 16 * PAGE_SIZE;
+
+/// The number of pages an allocation with `layout` is served with if it gets pages of its own.
+///
+/// That is the case for allocations of at least `PAGE_ALLOCATION_MIN_SIZE` bytes
+/// which need no more than page alignment.
+fn large_allocation_pages(layout: &Layout) -> Option<usize> {
+    if layout.size() >= PAGE_ALLOCATION_MIN_SIZE && layout.align() <= PAGE_SIZE {
+        Some((layout.size() + PAGE_SIZE - 1) / PAGE_SIZE)
+    } else {
+        None
+    }
+}
+
+/// The number of pages the Heap should grow by so that an allocation with `layout` fits.
+fn growth_pages(layout: &Layout) -> usize {
+    let pages = (layout.size() + layout.align() + PAGE_SIZE - 1) / PAGE_SIZE;
+
+    pages.max(MIN_HEAP_GROWTH_PAGES)
+}
+
+/// The number of attempts to acquire a `Locked` in debug builds before reporting a deadlock
+#[cfg(debug_assertions)]
+const DEADLOCK_SPINS: usize = 10_000_000;
+
 /// A wrapper around spin::Mutex to permit trait implementations.
 pub struct Locked<A> {
     inner: spin::Mutex<A>,
+    label: &'static str,
 }
 
 impl<A> Locked<A> {
     pub const fn new(inner: A) -> Self {
+        Self::with_label(inner, "unlabeled")
+    }
+
+    /// Creates a lock named `label` in deadlock reports
+    pub const fn with_label(inner: A, label: &'static str) -> Self {
         Locked {
             inner: spin::Mutex::new(inner),
+            label,
         }
     }
 
+    #[cfg(not(debug_assertions))]
     pub fn lock(&self) -> spin::MutexGuard<A> {
         self.inner.lock()
     }
-}
\ No newline at end of file
+
+    /// Acquires the lock, panicking if it stays taken for `DEADLOCK_SPINS` attempts.
+    ///
+    /// On a single core a lock held by an interrupted context is never released
+    /// while its interrupt handler spins, so such a hang is reported instead.
+    #[cfg(debug_assertions)]
+    pub fn lock(&self) -> spin::MutexGuard<A> {
+        for _ in 0..DEADLOCK_SPINS {
+            if let Some(guard) = self.inner.try_lock() {
+                return guard;
+            }
+            core::sync::atomic::spin_loop_hint();
+        }
+
+        panic!("possible deadlock: the {} lock could not be acquired", self.label);
+    }
+}
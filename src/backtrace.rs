@@ -0,0 +1,122 @@
+use crate::{
+    memory,
+    serial_println,
+};
+use x86_64::VirtAddr;
+
+/// The largest number of frames walked
+pub const MAX_FRAMES: usize = 32;
+
+/// The largest distance between two consecutive frame pointers considered plausible
+///
+/// A larger jump means the chain left the kernel stack, like at the frame
+/// of the bootloader, whose stack is not mapped.
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+/// Prints the return addresses of the calls leading here to the serial interface
+///
+/// The addresses can be resolved with the symbols of the kernel binary,
+/// e.g. with `addr2line -e <kernel> <address>`.
+pub fn backtrace() {
+    let mut addresses = [0; MAX_FRAMES];
+    let frames = capture(&mut addresses);
+
+    serial_println!("Backtrace:");
+    for (i, address) in addresses[..frames].iter().enumerate() {
+        serial_println!("  #{:<2} {:#018x}", i, address);
+    }
+}
+
+/// Stores the return addresses of the calls leading to the caller in `addresses`,
+/// innermost first, and returns their number
+///
+/// Walks the chain of saved `rbp` values, so the kernel must be built with frame pointers.
+/// The walk stops at a null, misaligned, unmapped or implausible frame pointer
+/// or when `addresses` is full, so a corrupt chain never faults.
+#[inline(never)]
+pub fn capture(addresses: &mut [u64]) -> usize {
+    let mut rbp: u64;
+    unsafe {
+        asm!("mov {}, rbp", out(reg) rbp);
+    }
+
+    let mut frames = 0;
+    while frames < addresses.len() && is_plausible_frame(rbp) {
+        // A frame starts with the saved rbp of the caller, followed by the return address
+        let (saved_rbp, return_address) = unsafe {
+            let frame = rbp as *const u64;
+            (*frame, *frame.add(1))
+        };
+        if return_address == 0 {
+            break;
+        }
+
+        addresses[frames] = return_address;
+        frames += 1;
+
+        if saved_rbp <= rbp || saved_rbp - rbp > MAX_FRAME_SIZE {
+            break;
+        }
+        rbp = saved_rbp;
+    }
+
+    frames
+}
+
+/// Checks if `rbp` can point to a frame, with the saved `rbp` and the return address mapped
+fn is_plausible_frame(rbp: u64) -> bool {
+    let is_readable = |address: u64| match VirtAddr::try_new(address) {
+        Ok(address) => memory::is_mapped(address),
+        Err(_)      => false,
+    };
+
+    rbp != 0 && rbp % 8 == 0 && is_readable(rbp) && rbp.checked_add(8).map_or(false, is_readable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An upper bound of the size of the functions below
+    const MAX_FUNCTION_SIZE: u64 = 0x1000;
+
+    // Tests are built without optimizations, so the calls below
+    // are not turned into jumps which would leave out their frames.
+
+    #[inline(never)]
+    fn outer(addresses: &mut [u64]) -> usize {
+        middle(addresses)
+    }
+
+    #[inline(never)]
+    fn middle(addresses: &mut [u64]) -> usize {
+        inner(addresses)
+    }
+
+    #[inline(never)]
+    fn inner(addresses: &mut [u64]) -> usize {
+        capture(addresses)
+    }
+
+    #[test_case]
+    fn capture_finds_the_return_addresses_of_nested_calls() {
+        let mut addresses = [0; MAX_FRAMES];
+        let frames = outer(&mut addresses);
+
+        assert!(frames >= 3);
+
+        let callers = [inner as usize as u64, middle as usize as u64, outer as usize as u64];
+        for (&address, &function) in addresses.iter().zip(callers.iter()) {
+            assert!(address > function && address - function < MAX_FUNCTION_SIZE);
+        }
+    }
+
+    #[test_case]
+    fn unmapped_or_non_canonical_frames_are_not_plausible() {
+        assert!(!is_plausible_frame(0x_5555_5555_0000));
+        assert!(!is_plausible_frame(0x_8000_0000_0000));
+
+        let local = 0u64;
+        assert!(is_plausible_frame(&local as *const u64 as u64));
+    }
+}
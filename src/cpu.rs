@@ -0,0 +1,151 @@
+use core::arch::x86_64::{
+    __cpuid,
+    CpuidResult,
+};
+
+/// The CPUID leaf reporting the vendor string
+const VENDOR_LEAF: u32 = 0;
+
+/// The CPUID leaf reporting the basic features
+const FEATURES_LEAF: u32 = 1;
+
+/// The CPUID leaf reporting the extended features
+const EXTENDED_FEATURES_LEAF: u32 = 0x8000_0001;
+
+/// A register of a CPUID result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    Ecx,
+    Edx,
+}
+
+/// The CPU features which can be detected with CPUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuFeature {
+    Sse3,
+    Ssse3,
+    Sse41,
+    Sse42,
+    X2Apic,
+    Popcnt,
+    Aes,
+    Xsave,
+    Avx,
+    Rdrand,
+    Hypervisor,
+    Fpu,
+    Pse,
+    Tsc,
+    Msr,
+    Pae,
+    Apic,
+    Pge,
+    Pat,
+    Mmx,
+    Fxsr,
+    Sse,
+    Sse2,
+    NoExecute,
+    Pages1GiB,
+    LongMode,
+}
+
+impl CpuFeature {
+    /// Returns the CPUID leaf, the register and the bit reporting the feature
+    fn location(self) -> (u32, Register, u32) {
+        use CpuFeature::*;
+        use Register::*;
+
+        match self {
+            Sse3       => (FEATURES_LEAF, Ecx, 0),
+            Ssse3      => (FEATURES_LEAF, Ecx, 9),
+            Sse41      => (FEATURES_LEAF, Ecx, 19),
+            Sse42      => (FEATURES_LEAF, Ecx, 20),
+            X2Apic     => (FEATURES_LEAF, Ecx, 21),
+            Popcnt     => (FEATURES_LEAF, Ecx, 23),
+            Aes        => (FEATURES_LEAF, Ecx, 25),
+            Xsave      => (FEATURES_LEAF, Ecx, 26),
+            Avx        => (FEATURES_LEAF, Ecx, 28),
+            Rdrand     => (FEATURES_LEAF, Ecx, 30),
+            Hypervisor => (FEATURES_LEAF, Ecx, 31),
+            Fpu        => (FEATURES_LEAF, Edx, 0),
+            Pse        => (FEATURES_LEAF, Edx, 3),
+            Tsc        => (FEATURES_LEAF, Edx, 4),
+            Msr        => (FEATURES_LEAF, Edx, 5),
+            Pae        => (FEATURES_LEAF, Edx, 6),
+            Apic       => (FEATURES_LEAF, Edx, 9),
+            Pge        => (FEATURES_LEAF, Edx, 13),
+            Pat        => (FEATURES_LEAF, Edx, 16),
+            Mmx        => (FEATURES_LEAF, Edx, 23),
+            Fxsr       => (FEATURES_LEAF, Edx, 24),
+            Sse        => (FEATURES_LEAF, Edx, 25),
+            Sse2       => (FEATURES_LEAF, Edx, 26),
+            NoExecute  => (EXTENDED_FEATURES_LEAF, Edx, 20),
+            Pages1GiB  => (EXTENDED_FEATURES_LEAF, Edx, 26),
+            LongMode   => (EXTENDED_FEATURES_LEAF, Edx, 29),
+        }
+    }
+}
+
+fn cpuid(leaf: u32) -> CpuidResult {
+    unsafe { __cpuid(leaf) }
+}
+
+/// Returns the CPU vendor string, like `GenuineIntel` or `AuthenticAMD`
+pub fn vendor() -> [u8; 12] {
+    let result = cpuid(VENDOR_LEAF);
+    let mut vendor = [0; 12];
+
+    vendor[0..4].copy_from_slice(&result.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&result.edx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&result.ecx.to_le_bytes());
+
+    vendor
+}
+
+/// Returns the ID the Local APIC of the CPU had at power up
+pub fn initial_apic_id() -> u8 {
+    (cpuid(FEATURES_LEAF).ebx >> 24) as u8
+}
+
+/// Checks if the CPU supports the given feature
+pub fn has_feature(feature: CpuFeature) -> bool {
+    let (leaf, register, bit) = feature.location();
+
+    let max_leaf = cpuid(leaf & 0x8000_0000).eax;
+    if leaf > max_leaf {
+        return false;
+    }
+
+    let result = cpuid(leaf);
+    let value = match register {
+        Register::Ecx => result.ecx,
+        Register::Edx => result.edx,
+    };
+
+    value & (1 << bit) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn vendor_is_a_known_one() {
+        let known_vendors: [&[u8; 12]; 5] = [
+            b"GenuineIntel",
+            b"AuthenticAMD",
+            b"TCGTCGTCGTCG",
+            b"HygonGenuine",
+            b"CentaurHauls",
+        ];
+
+        assert!(known_vendors.contains(&&vendor()));
+    }
+
+    #[test_case]
+    fn universal_features_are_present() {
+        assert!(has_feature(CpuFeature::Fpu));
+        assert!(has_feature(CpuFeature::LongMode));
+    }
+}
@@ -11,13 +11,17 @@ use x86_64::{
         },
     },
 };
+use alloc::boxed::Box;
 use lazy_static::lazy_static;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 1;
+pub const PAGE_FAULT_IST_INDEX: u16 = 2;
 
 #[allow(dead_code)]
 struct TaskStateSegmentWithStacks {
     interrupt_stacks: ist::InterruptStackTable,
+    privilege_stack: Box<ist::InterruptStack>,
     tss: TaskStateSegment,
 }
 
@@ -26,11 +30,23 @@ lazy_static! {
         let interrupt_stacks = ist::InterruptStackTable::new();
         let mut tss = TaskStateSegment::new();
 
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            let stack = interrupt_stacks[0]
-                        .as_deref()
-                        .expect("Empty InterruptStackTable entry");
-            let stack_start = VirtAddr::from_ptr(stack);
+        for &index in [DOUBLE_FAULT_IST_INDEX, MACHINE_CHECK_IST_INDEX, PAGE_FAULT_IST_INDEX].iter() {
+            let index = usize::from(index);
+
+            tss.interrupt_stack_table[index] = {
+                let stack = interrupt_stacks[index]
+                            .as_deref()
+                            .expect("Empty InterruptStackTable entry");
+                let stack_start = VirtAddr::from_ptr(stack);
+                let stack_end = stack_start + ist::STACK_SIZE;
+
+                stack_end
+            };
+        }
+
+        let privilege_stack = Box::new(ist::InterruptStack::new());
+        tss.privilege_stack_table[0] = {
+            let stack_start = VirtAddr::from_ptr(&*privilege_stack);
             let stack_end = stack_start + ist::STACK_SIZE;
 
             stack_end
@@ -38,6 +54,7 @@ lazy_static! {
 
         TaskStateSegmentWithStacks {
             interrupt_stacks,
+            privilege_stack,
             tss,
         }
     };
@@ -48,11 +65,15 @@ lazy_static! {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
         let tss_selector  = gdt.add_entry(Descriptor::tss_segment(&TSS.tss));
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
         
         GdtWithSelectors{
             gdt,
             tss_selector,
             code_selector, 
+            user_code_selector,
+            user_data_selector,
         }
     };
 }
@@ -61,6 +82,8 @@ struct GdtWithSelectors {
     gdt: GlobalDescriptorTable,
     tss_selector: SegmentSelector,
     code_selector: SegmentSelector,
+    user_code_selector: SegmentSelector,
+    user_data_selector: SegmentSelector,
 }
 
 /// Sets up and loads the Global descriptor table
@@ -75,4 +98,38 @@ pub fn init() {
         set_cs(GDT.code_selector);
         load_tss(GDT.tss_selector);
     }
-}
\ No newline at end of file
+}
+
+/// Returns the selector of the ring 3 code segment
+pub fn user_code_selector() -> SegmentSelector {
+    GDT.user_code_selector
+}
+
+/// Returns the selector of the ring 3 data and stack segment
+pub fn user_data_selector() -> SegmentSelector {
+    GDT.user_data_selector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x86_64::PrivilegeLevel;
+
+    #[test_case]
+    fn user_selectors_have_rpl_3() {
+        assert_eq!(user_code_selector().rpl(), PrivilegeLevel::Ring3);
+        assert_eq!(user_data_selector().rpl(), PrivilegeLevel::Ring3);
+    }
+
+    #[test_case]
+    fn user_data_selector_can_be_loaded() {
+        use x86_64::instructions::segmentation::load_ds;
+
+        let ds: u16;
+        unsafe {
+            asm!("mov {0:x}, ds", out(reg) ds);
+            load_ds(user_data_selector());
+            load_ds(SegmentSelector(ds));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use core::fmt;
+use crate::print;
+
+/// The number of bytes dumped if no other limit is given
+pub const DEFAULT_LIMIT: usize = 256;
+
+/// The number of bytes on each row of a dump
+const BYTES_PER_ROW: usize = 16;
+
+/// Prints the `len` bytes starting at `ptr`, at most `DEFAULT_LIMIT` of them
+///
+/// ## Safety
+///
+/// The first `len.min(DEFAULT_LIMIT)` bytes must be mapped and readable,
+/// the rest of them are never read.
+pub unsafe fn hexdump(ptr: *const u8, len: usize) {
+    let shown = core::slice::from_raw_parts(ptr, len.min(DEFAULT_LIMIT));
+
+    print!("{}", Hexdump::of_prefix(shown, len));
+}
+
+/// Prints the bytes, at most `DEFAULT_LIMIT` of them
+pub fn hexdump_slice(bytes: &[u8]) {
+    hexdump_limited(bytes, DEFAULT_LIMIT);
+}
+
+/// Prints the bytes, at most `limit` of them
+pub fn hexdump_limited(bytes: &[u8], limit: usize) {
+    print!("{}", Hexdump::new(bytes, limit));
+}
+
+/// Bytes formatted in rows of their offset, hex values and ASCII characters,
+/// like `00000010: 48 69 00 ...  |Hi.|`
+///
+/// Non-printable bytes are shown as `.` in the ASCII column.
+/// Bytes past the limit are only counted.
+pub struct Hexdump<'a> {
+    bytes: &'a [u8],
+    limit: usize,
+    /// The number of bytes dumped, of which `bytes` may be only the first ones
+    len: usize,
+}
+
+impl<'a> Hexdump<'a> {
+    pub fn new(bytes: &'a [u8], limit: usize) -> Self {
+        Hexdump {
+            bytes,
+            limit,
+            len: bytes.len(),
+        }
+    }
+
+    /// A dump of `len` bytes showing only `shown`, the first of them
+    fn of_prefix(shown: &'a [u8], len: usize) -> Self {
+        Hexdump {
+            bytes: shown,
+            limit: shown.len(),
+            len,
+        }
+    }
+}
+
+impl fmt::Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shown = &self.bytes[..self.bytes.len().min(self.limit)];
+
+        for (i, row) in shown.chunks(BYTES_PER_ROW).enumerate() {
+            write!(f, "{:08x}: ", i * BYTES_PER_ROW)?;
+            for byte in row {
+                write!(f, "{:02x} ", byte)?;
+            }
+            for _ in row.len()..BYTES_PER_ROW {
+                write!(f, "   ")?;
+            }
+
+            write!(f, " |")?;
+            for &byte in row {
+                let c = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f, "|")?;
+        }
+
+        let hidden = self.len - shown.len();
+        if hidden > 0 {
+            writeln!(f, "... {} more bytes", hidden)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test_case]
+    fn dump_has_hex_and_ascii_columns() {
+        let bytes = b"Hello, world!\n\x00\xffmyos";
+
+        let dump = format!("{}", Hexdump::new(bytes, DEFAULT_LIMIT));
+        let mut rows = dump.lines();
+
+        assert_eq!(
+            rows.next(),
+            Some("00000000: 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 ff  |Hello, world!...|")
+        );
+        assert_eq!(
+            rows.next(),
+            Some("00000010: 6d 79 6f 73                                      |myos|")
+        );
+        assert_eq!(rows.next(), None);
+    }
+
+    #[test_case]
+    fn dump_stops_at_the_limit() {
+        let bytes = [0; 100];
+
+        let dump = format!("{}", Hexdump::new(&bytes, 32));
+
+        assert_eq!(dump.lines().count(), 3);
+        assert!(dump.ends_with("... 68 more bytes\n"));
+    }
+
+    #[test_case]
+    fn dump_of_a_prefix_counts_the_rest() {
+        let bytes = [0; 32];
+
+        let dump = format!("{}", Hexdump::of_prefix(&bytes, 1000));
+
+        assert_eq!(dump.lines().count(), 3);
+        assert!(dump.ends_with("... 968 more bytes\n"));
+    }
+}
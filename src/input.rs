@@ -0,0 +1,313 @@
+use pc_keyboard::DecodedKey;
+use crate::{
+    print,
+    println,
+    keyboard,
+    port_io::{
+        MachinePorts,
+        PortIo,
+    },
+    ps2,
+    serial,
+    time,
+};
+
+const BACKSPACE: char = '\u{8}';
+
+/// The byte terminals send for the Backspace key
+const DELETE: u8 = 0x7f;
+
+/// The number of times the status is polled before giving up on the keyboard
+const MAX_STATUS_POLLS: usize = 100_000;
+
+/// The response of the keyboard to a command byte it accepted
+const ACK: u8 = 0xfa;
+
+const SET_LEDS_COMMAND: u8 = 0xed;
+const SET_TYPEMATIC_COMMAND: u8 = 0xf3;
+
+const SCROLL_LOCK_LED: u8 = 0b001;
+const NUM_LOCK_LED: u8 = 0b010;
+const CAPS_LOCK_LED: u8 = 0b100;
+
+/// The errors of sending a command to the PS/2 keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// The controller was busy or the keyboard did not respond in time
+    Timeout,
+    /// The keyboard responded with something other than an ACK
+    UnexpectedResponse(u8),
+}
+
+/// The time a key must be held down before it starts repeating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RepeatDelay {
+    Ms250 = 0,
+    Ms500,
+    Ms750,
+    Ms1000,
+}
+
+/// The number of characters per second a held down key repeats at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RepeatRate {
+    Cps30 = 0x00,
+    Cps24 = 0x02,
+    Cps20 = 0x04,
+    Cps15 = 0x08,
+    Cps12 = 0x0a,
+    Cps10 = 0x0c,
+    Cps8  = 0x0f,
+    Cps6  = 0x12,
+    Cps5  = 0x14,
+    Cps4  = 0x17,
+    Cps3  = 0x1a,
+    Cps2  = 0x1f,
+}
+
+/// Turns the Caps, Num and Scroll Lock LEDs of the keyboard on or off
+pub fn set_leds(caps: bool, num: bool, scroll: bool) -> Result<(), Ps2Error> {
+    send_command(&mut MachinePorts, SET_LEDS_COMMAND, led_mask(caps, num, scroll))
+}
+
+/// Sets how long a key must be held down before it repeats and how fast it does
+pub fn set_repeat_rate(delay: RepeatDelay, rate: RepeatRate) -> Result<(), Ps2Error> {
+    send_command(&mut MachinePorts, SET_TYPEMATIC_COMMAND, typematic_byte(delay, rate))
+}
+
+/// Encodes the delay in bits 5-6 and the rate in bits 0-4
+fn typematic_byte(delay: RepeatDelay, rate: RepeatRate) -> u8 {
+    (delay as u8) << 5 | rate as u8
+}
+
+fn led_mask(caps: bool, num: bool, scroll: bool) -> u8 {
+    let led = |on: bool, led: u8| if on { led } else { 0 };
+
+    led(caps, CAPS_LOCK_LED) | led(num, NUM_LOCK_LED) | led(scroll, SCROLL_LOCK_LED)
+}
+
+/// Sends a command byte and its data byte to the keyboard, waiting for the ACK of each.
+///
+/// Interrupts are disabled meanwhile, so the keyboard interrupt handler
+/// cannot take the ACKs for scancodes.
+fn send_command<P: PortIo>(ports: &mut P, command: u8, data: u8) -> Result<(), Ps2Error> {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        send_byte(ports, command)?;
+        send_byte(ports, data)
+    })
+}
+
+fn send_byte<P: PortIo>(ports: &mut P, byte: u8) -> Result<(), Ps2Error> {
+    wait_for_status(ports, |status| status & ps2::INPUT_BUFFER_FULL == 0)?;
+    ports.write_u8(ps2::DATA_PORT, byte);
+
+    wait_for_status(ports, |status| status & ps2::OUTPUT_BUFFER_FULL != 0)?;
+    match ports.read_u8(ps2::DATA_PORT) {
+        ACK      => Ok(()),
+        response => Err(Ps2Error::UnexpectedResponse(response)),
+    }
+}
+
+fn wait_for_status<P, F>(ports: &mut P, is_ready: F) -> Result<(), Ps2Error>
+where
+    P: PortIo,
+    F: Fn(u8) -> bool,
+{
+    for _ in 0..MAX_STATUS_POLLS {
+        if is_ready(ports.read_u8(ps2::STATUS_PORT)) {
+            return Ok(());
+        }
+    }
+
+    Err(Ps2Error::Timeout)
+}
+
+/// Reads a line of keyboard or serial input into `buf`, echoing it to the screen.
+///
+/// Blocks until Enter is pressed and returns the number of bytes written to `buf`.
+/// The line is stored UTF-8 encoded, without the newline.
+/// Backspace erases the last character and input which does not fit in `buf` is ignored.
+pub fn read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+
+    loop {
+        match next_char() {
+            '\n' => {
+                println!();
+                return len;
+            },
+            BACKSPACE => {
+                let erased = erase_last_char(&buf[..len]);
+                len -= erased;
+                for _ in 0..erased {
+                    print!("{}", BACKSPACE);
+                }
+            },
+            c => {
+                let mut encoded = [0; 4];
+                let encoded = c.encode_utf8(&mut encoded).as_bytes();
+
+                if len + encoded.len() <= buf.len() {
+                    buf[len..len + encoded.len()].copy_from_slice(encoded);
+                    len += encoded.len();
+                    print!("{}", c);
+                }
+            },
+        }
+    }
+}
+
+/// Returns the number of bytes the last UTF-8 encoded character of `line` takes
+fn erase_last_char(line: &[u8]) -> usize {
+    let is_continuation_byte = |b: &u8| b & 0b1100_0000 == 0b1000_0000;
+
+    match line.iter().rposition(|b| !is_continuation_byte(b)) {
+        Some(start) => line.len() - start,
+        None        => line.len(),
+    }
+}
+
+/// Halts until a key producing a character is pressed or a character is received through serial
+///
+/// Serial input does not raise interrupts, so it is only noticed on the next one.
+fn next_char() -> char {
+    loop {
+        match keyboard::poll_key() {
+            Some(DecodedKey::Unicode(c)) => return c,
+            Some(DecodedKey::RawKey(_))  => continue,
+            None                         => {},
+        }
+
+        match serial::serial_try_read() {
+            Some(byte) => if let Some(c) = serial_char(byte) {
+                return c;
+            },
+            None => {
+                x86_64::instructions::hlt();
+                time::run_due_timeouts();
+            },
+        }
+    }
+}
+
+/// The character a byte received through serial stands for
+///
+/// Terminals send a carriage return for Enter and DEL for Backspace.
+/// Non-ASCII bytes are ignored.
+fn serial_char(byte: u8) -> Option<char> {
+    match byte {
+        b'\r'                => Some('\n'),
+        DELETE               => Some(BACKSPACE),
+        _ if byte.is_ascii() => Some(char::from(byte)),
+        _                    => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::port_io::RecordingPorts;
+
+    /// Ports of a controller which is ready for each byte and gets the keyboard's responses to them
+    fn keyboard_responding(responses: &[u8]) -> RecordingPorts {
+        let mut ports = RecordingPorts::new();
+        for &response in responses {
+            ports.queue_reads(ps2::STATUS_PORT, &[0, ps2::OUTPUT_BUFFER_FULL]);
+            ports.queue_reads(ps2::DATA_PORT, &[response]);
+        }
+
+        ports
+    }
+
+    #[test_case]
+    fn set_leds_command_is_sent_with_the_led_mask() {
+        let mut ports = keyboard_responding(&[ACK, ACK]);
+
+        let result = send_command(&mut ports, SET_LEDS_COMMAND, led_mask(true, false, true));
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(ports.writes, [(0x60, 0xed), (0x60, 0b101)]);
+    }
+
+    #[test_case]
+    fn set_typematic_command_is_sent_with_the_encoded_rate() {
+        let mut ports = keyboard_responding(&[ACK, ACK]);
+
+        let data = typematic_byte(RepeatDelay::Ms500, RepeatRate::Cps10);
+        let result = send_command(&mut ports, SET_TYPEMATIC_COMMAND, data);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(ports.writes, [(0x60, 0xf3), (0x60, 0b01_01100)]);
+        assert_eq!(ports.reads(0x60), 2);
+    }
+
+    #[test_case]
+    fn typematic_byte_covers_the_extreme_settings() {
+        assert_eq!(typematic_byte(RepeatDelay::Ms250, RepeatRate::Cps30), 0x00);
+        assert_eq!(typematic_byte(RepeatDelay::Ms1000, RepeatRate::Cps2), 0x7f);
+    }
+
+    #[test_case]
+    fn command_without_ack_fails() {
+        let mut ports = keyboard_responding(&[0xfe]);
+        assert_eq!(
+            send_command(&mut ports, SET_LEDS_COMMAND, 0),
+            Err(Ps2Error::UnexpectedResponse(0xfe))
+        );
+
+        let mut ports = keyboard_responding(&[]);
+        assert_eq!(send_command(&mut ports, SET_LEDS_COMMAND, 0), Err(Ps2Error::Timeout));
+        assert_eq!(ports.reads(0x64), 1 + MAX_STATUS_POLLS);
+    }
+
+    #[test_case]
+    fn serial_bytes_are_translated_to_line_editing_characters() {
+        assert_eq!(serial_char(b'\r'), Some('\n'));
+        assert_eq!(serial_char(0x7f), Some(BACKSPACE));
+        assert_eq!(serial_char(b'a'), Some('a'));
+        assert_eq!(serial_char(0xc3), None);
+    }
+
+    fn type_scancodes(scancodes: &[u8]) {
+        for &scancode in scancodes {
+            keyboard::add_scancode(scancode);
+        }
+    }
+
+    #[test_case]
+    fn read_line_returns_the_typed_line() {
+        // h, i, Enter
+        type_scancodes(&[0x23, 0xa3, 0x17, 0x97, 0x1c, 0x9c]);
+
+        let mut buf = [0; 16];
+        let len = read_line(&mut buf);
+
+        assert_eq!(&buf[..len], b"hi");
+    }
+
+    #[test_case]
+    fn read_line_erases_on_backspace() {
+        // h, i, Backspace, Enter
+        type_scancodes(&[0x23, 0xa3, 0x17, 0x97, 0x0e, 0x8e, 0x1c, 0x9c]);
+
+        let mut buf = [0; 16];
+        let len = read_line(&mut buf);
+
+        assert_eq!(&buf[..len], b"h");
+    }
+
+    #[test_case]
+    fn read_line_ignores_input_past_the_buffer() {
+        // h, i, Enter
+        type_scancodes(&[0x23, 0xa3, 0x17, 0x97, 0x1c, 0x9c]);
+
+        let mut buf = [0; 1];
+        let len = read_line(&mut buf);
+
+        assert_eq!(&buf[..len], b"h");
+    }
+}
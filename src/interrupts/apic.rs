@@ -0,0 +1,175 @@
+use super::{
+    irq,
+    InterruptIndex,
+};
+use crate::{
+    cpu::{
+        self,
+        CpuFeature,
+    },
+    memory,
+    mmio::Mmio,
+};
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use lazy_static::lazy_static;
+use x86_64::{
+    PhysAddr,
+    VirtAddr,
+    registers::model_specific::Msr,
+    structures::{
+        idt::InterruptStackFrame,
+        paging::PageTableFlags,
+    },
+};
+
+/// The vector of the interrupts the Local APIC raises spuriously
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+/// The MSR holding the physical address of the Local APIC
+const APIC_BASE_MSR: u32 = 0x1b;
+
+/// The bits of the APIC base MSR holding the physical address
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// The physical address of the first IO-APIC on PCs
+///
+/// It is not looked up in the ACPI tables, which are not parsed.
+const IO_APIC_ADDRESS: u64 = 0xfec0_0000;
+
+/// The offsets of the Local APIC registers from its base
+const ID_REGISTER: usize = 0x20;
+const EOI_REGISTER: usize = 0xb0;
+const SPURIOUS_VECTOR_REGISTER: usize = 0xf0;
+
+/// The spurious vector register bit which enables the Local APIC
+const APIC_SOFTWARE_ENABLE: u32 = 0x100;
+
+/// The offsets of the IO-APIC register select and data window registers from its base
+const IO_REGISTER_SELECT: usize = 0x00;
+const IO_WINDOW: usize = 0x10;
+
+/// The index of the first redirection table register of the IO-APIC
+const REDIRECTION_TABLE: u32 = 0x10;
+
+/// The IO-APIC inputs of the timer and the keyboard
+///
+/// On PCs the PIT is connected to input 2, as described by the interrupt
+/// source override the ACPI tables have for it, and the keyboard to input 1.
+const TIMER_GSI: u32 = 2;
+const KEYBOARD_GSI: u32 = 1;
+
+/// Set once the interrupts are routed through the APICs instead of the PICs
+static APIC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// The virtual address of the Local APIC registers
+    static ref LOCAL_APIC: VirtAddr = {
+        let base = unsafe { Msr::new(APIC_BASE_MSR).read() } & APIC_BASE_ADDRESS_MASK;
+
+        map_registers(PhysAddr::new(base))
+    };
+
+    /// The virtual address of the IO-APIC registers
+    static ref IO_APIC: VirtAddr = map_registers(PhysAddr::new(IO_APIC_ADDRESS));
+}
+
+fn map_registers(phys_start: PhysAddr) -> VirtAddr {
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+
+    memory::with_kernel_memory(|mapper, frame_allocator| {
+        memory::map_physical_region(mapper, frame_allocator, phys_start, memory::PAGE_SIZE, flags)
+    })
+    .expect("Mapping the APIC registers failed")
+}
+
+/// Checks if the CPU has a Local APIC
+pub fn is_present() -> bool {
+    cpu::has_feature(CpuFeature::Apic)
+}
+
+/// Checks if the interrupts are routed through the APICs
+pub fn is_enabled() -> bool {
+    APIC_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Masks all lines of the PICs, enables the Local APIC and routes
+/// the timer and the keyboard interrupts through the IO-APIC
+///
+/// The PICs must be initialised first, so that interrupts they raise
+/// before being masked do not land on the exception vectors.
+/// The other IRQ lines are not routed: handlers registered with
+/// `register_irq_handler` are not called afterwards.
+///
+/// ## Safety
+///
+/// The CPU must have a Local APIC and the IO-APIC must be at its standard address.
+pub(super) unsafe fn enable() {
+    irq::mask_all();
+
+    write_local(SPURIOUS_VECTOR_REGISTER, APIC_SOFTWARE_ENABLE | u32::from(SPURIOUS_VECTOR));
+
+    let apic_id = local_apic_id();
+    route(TIMER_GSI, InterruptIndex::Timer.as_u8(), apic_id);
+    route(KEYBOARD_GSI, InterruptIndex::Keyboard.as_u8(), apic_id);
+
+    APIC_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Reads the ID of the Local APIC of the CPU
+///
+/// Panics if the CPU has no Local APIC.
+pub fn local_apic_id() -> u8 {
+    assert!(is_present(), "The CPU has no Local APIC");
+
+    (unsafe { read_local(ID_REGISTER) } >> 24) as u8
+}
+
+/// Signals the end of the current interrupt to the Local APIC
+pub(super) fn end_of_interrupt() {
+    unsafe {
+        write_local(EOI_REGISTER, 0);
+    }
+}
+
+/// Routes the IO-APIC input as an edge-triggered, active high, fixed interrupt
+/// with the given vector to the Local APIC with the given ID
+unsafe fn route(gsi: u32, vector: u8, apic_id: u8) {
+    let register = REDIRECTION_TABLE + 2 * gsi;
+
+    write_io(register + 1, u32::from(apic_id) << 24);
+    write_io(register, u32::from(vector));
+}
+
+unsafe fn read_local(offset: usize) -> u32 {
+    Mmio::<u32>::new(*LOCAL_APIC + offset).read()
+}
+
+unsafe fn write_local(offset: usize, value: u32) {
+    Mmio::new(*LOCAL_APIC + offset).write(value);
+}
+
+unsafe fn write_io(register: u32, value: u32) {
+    Mmio::new(*IO_APIC + IO_REGISTER_SELECT).write(register);
+    Mmio::new(*IO_APIC + IO_WINDOW).write(value);
+}
+
+/// Ignores a spurious interrupt, which must not be acknowledged
+pub(super) extern "x86-interrupt"
+fn spurious_interrupt_handler(_: &mut InterruptStackFrame) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn local_apic_id_matches_the_one_reported_by_cpuid() {
+        if !is_present() {
+            return;
+        }
+
+        assert_eq!(local_apic_id(), cpu::initial_apic_id());
+    }
+}
@@ -0,0 +1,125 @@
+use x86_64::structures::idt::{
+    HandlerFunc,
+    InterruptDescriptorTable,
+    InterruptStackFrame,
+};
+use crate::{
+    println,
+    print,
+    hlt_loop,
+};
+
+/// The first vector after the CPU exceptions
+const FIRST_INTERRUPT_VECTOR: usize = 32;
+
+/// Whether the fallback handlers return instead of halting, so that tests can continue
+#[cfg(test)]
+static RESUME_AFTER_UNHANDLED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Reports an interrupt without a handler of its own, then halts
+fn unhandled(vector: u8, stack_frame: &InterruptStackFrame, error_code: Option<u64>) {
+    println!("EXCEPTION: UNHANDLED VECTOR {}", vector);
+    if let Some(error_code) = error_code {
+        println!("Error Code: {:#x}", error_code);
+    }
+    println!("{:#?}", stack_frame);
+
+    #[cfg(test)]
+    {
+        if RESUME_AFTER_UNHANDLED.load(core::sync::atomic::Ordering::SeqCst) {
+            return;
+        }
+    }
+
+    hlt_loop();
+}
+
+extern "x86-interrupt"
+fn fallback_handler<const VECTOR: u8>(stack_frame: &mut InterruptStackFrame) {
+    unhandled(VECTOR, stack_frame, None);
+}
+
+extern "x86-interrupt"
+fn fallback_handler_with_error_code<const VECTOR: u8>(stack_frame: &mut InterruptStackFrame, error_code: u64) {
+    unhandled(VECTOR, stack_frame, Some(error_code));
+}
+
+/// The fallback handlers of the 16 vectors starting at `$high * 16`
+macro_rules! fallback_handlers_row {
+    ($high:literal) => {
+        [
+            fallback_handler::<{ $high * 16 }>,      fallback_handler::<{ $high * 16 + 1 }>,
+            fallback_handler::<{ $high * 16 + 2 }>,  fallback_handler::<{ $high * 16 + 3 }>,
+            fallback_handler::<{ $high * 16 + 4 }>,  fallback_handler::<{ $high * 16 + 5 }>,
+            fallback_handler::<{ $high * 16 + 6 }>,  fallback_handler::<{ $high * 16 + 7 }>,
+            fallback_handler::<{ $high * 16 + 8 }>,  fallback_handler::<{ $high * 16 + 9 }>,
+            fallback_handler::<{ $high * 16 + 10 }>, fallback_handler::<{ $high * 16 + 11 }>,
+            fallback_handler::<{ $high * 16 + 12 }>, fallback_handler::<{ $high * 16 + 13 }>,
+            fallback_handler::<{ $high * 16 + 14 }>, fallback_handler::<{ $high * 16 + 15 }>,
+        ]
+    };
+}
+
+/// The fallback handlers of the vectors after the CPU exceptions, in rows of 16
+const FALLBACK_HANDLERS: [[HandlerFunc; 16]; 14] = [
+    fallback_handlers_row!(2),
+    fallback_handlers_row!(3),
+    fallback_handlers_row!(4),
+    fallback_handlers_row!(5),
+    fallback_handlers_row!(6),
+    fallback_handlers_row!(7),
+    fallback_handlers_row!(8),
+    fallback_handlers_row!(9),
+    fallback_handlers_row!(10),
+    fallback_handlers_row!(11),
+    fallback_handlers_row!(12),
+    fallback_handlers_row!(13),
+    fallback_handlers_row!(14),
+    fallback_handlers_row!(15),
+];
+
+/// Sets a handler reporting its vector for every vector which can have one
+///
+/// It must be called before the specific handlers are set, which then replace the fallbacks.
+/// That turns an interrupt without a handler into a report instead of a triple fault.
+pub(super) fn set_fallback_handlers(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(fallback_handler::<0>);
+    idt.debug.set_handler_fn(fallback_handler::<1>);
+    idt.non_maskable_interrupt.set_handler_fn(fallback_handler::<2>);
+    idt.breakpoint.set_handler_fn(fallback_handler::<3>);
+    idt.overflow.set_handler_fn(fallback_handler::<4>);
+    idt.bound_range_exceeded.set_handler_fn(fallback_handler::<5>);
+    idt.invalid_opcode.set_handler_fn(fallback_handler::<6>);
+    idt.device_not_available.set_handler_fn(fallback_handler::<7>);
+    idt.invalid_tss.set_handler_fn(fallback_handler_with_error_code::<10>);
+    idt.segment_not_present.set_handler_fn(fallback_handler_with_error_code::<11>);
+    idt.stack_segment_fault.set_handler_fn(fallback_handler_with_error_code::<12>);
+    idt.general_protection_fault.set_handler_fn(fallback_handler_with_error_code::<13>);
+    idt.x87_floating_point.set_handler_fn(fallback_handler::<16>);
+    idt.alignment_check.set_handler_fn(fallback_handler_with_error_code::<17>);
+    idt.simd_floating_point.set_handler_fn(fallback_handler::<19>);
+    idt.virtualization.set_handler_fn(fallback_handler::<20>);
+    idt.security_exception.set_handler_fn(fallback_handler_with_error_code::<30>);
+
+    for vector in FIRST_INTERRUPT_VECTOR..256 {
+        idt[vector].set_handler_fn(FALLBACK_HANDLERS[vector / 16 - 2][vector % 16]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::Ordering;
+    use crate::vga_buffer;
+
+    #[test_case]
+    fn interrupt_without_a_handler_reports_its_vector() {
+        RESUME_AFTER_UNHANDLED.store(true, Ordering::SeqCst);
+        unsafe {
+            asm!("int 0x42");
+        }
+        RESUME_AFTER_UNHANDLED.store(false, Ordering::SeqCst);
+
+        assert!(vga_buffer::screen_contains("EXCEPTION: UNHANDLED VECTOR 66"));
+    }
+}
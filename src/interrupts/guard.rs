@@ -0,0 +1,76 @@
+use x86_64::instructions::interrupts;
+
+/// Disables interrupts while it lives
+///
+/// Dropping it restores the state interrupts were in when it was created,
+/// so guards can be nested: interrupts are only enabled again once the outermost one is dropped.
+#[must_use = "interrupts are enabled again right away if the guard is not kept"]
+pub struct InterruptGuard {
+    were_enabled: bool,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        InterruptGuard {
+            were_enabled,
+        }
+    }
+}
+
+impl Default for InterruptGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.were_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Runs `f` with interrupts disabled, restoring their previous state afterwards
+pub fn critical_section<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let _guard = InterruptGuard::new();
+
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn interrupts_are_enabled_again_only_after_the_outermost_guard() {
+        assert!(interrupts::are_enabled());
+
+        let outer = InterruptGuard::new();
+        let inner = InterruptGuard::new();
+        assert!(!interrupts::are_enabled());
+
+        drop(inner);
+        assert!(!interrupts::are_enabled());
+
+        drop(outer);
+        assert!(interrupts::are_enabled());
+    }
+
+    #[test_case]
+    fn critical_section_returns_the_result_of_its_closure() {
+        let result = critical_section(|| {
+            let nested = critical_section(interrupts::are_enabled);
+            (nested, interrupts::are_enabled())
+        });
+
+        assert_eq!(result, (false, false));
+        assert!(interrupts::are_enabled());
+    }
+}
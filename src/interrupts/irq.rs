@@ -0,0 +1,295 @@
+use super::{
+    critical_section,
+    PICS,
+    PIC_1_OFFSET,
+};
+use crate::port_io::{
+    MachinePorts,
+    PortIo,
+};
+use x86_64::{
+    instructions::port::Port,
+    structures::idt::{
+        InterruptDescriptorTable,
+        InterruptStackFrame,
+    },
+};
+
+/// The number of IRQ lines of the chained PICs
+const IRQ_LINES: usize = 16;
+
+/// The IRQ line the slave PIC is cascaded through
+const CASCADE_IRQ: u8 = 2;
+
+const PIC_1_COMMAND_PORT: u16 = 0x20;
+const PIC_1_DATA_PORT: u16 = 0x21;
+const PIC_2_COMMAND_PORT: u16 = 0xA0;
+const PIC_2_DATA_PORT: u16 = 0xA1;
+
+/// The OCW3 command selecting the in-service register for the next read of the command port
+const READ_IN_SERVICE_REGISTER: u8 = 0x0b;
+
+/// The non-specific end of interrupt command
+const END_OF_INTERRUPT: u8 = 0x20;
+
+/// The IRQ lines a PIC raises spurious interrupts on, the lowest priority one of each
+const MASTER_SPURIOUS_IRQ: u8 = 7;
+const SLAVE_SPURIOUS_IRQ: u8 = 15;
+
+/// A handler of a hardware interrupt, registered at runtime
+pub type IrqHandler = fn(&mut InterruptStackFrame);
+
+static HANDLERS: spin::Mutex<[Option<IrqHandler>; IRQ_LINES]> = spin::Mutex::new([None; IRQ_LINES]);
+
+/// Registers `handler` to be called on each interrupt of the given IRQ line
+/// and unmasks that line on the PIC.
+///
+/// The end of the interrupt is signalled after the handler returns.
+/// Panics if the line is used by the kernel itself (the timer, the keyboard and the cascade).
+pub fn register_irq_handler(irq: u8, handler: IrqHandler) {
+    assert!(is_dispatched(irq), "IRQ {} cannot have a custom handler", irq);
+
+    critical_section(|| {
+        HANDLERS.lock()[usize::from(irq)] = Some(handler);
+        unmask_irq(irq);
+    });
+}
+
+/// Checks if the IRQ line is handled through the dispatch table
+fn is_dispatched(irq: u8) -> bool {
+    irq > CASCADE_IRQ && usize::from(irq) < IRQ_LINES
+}
+
+/// Masks all IRQ lines of both PICs
+pub(super) fn mask_all() {
+    unsafe {
+        Port::<u8>::new(PIC_1_DATA_PORT).write(0xff);
+        Port::<u8>::new(PIC_2_DATA_PORT).write(0xff);
+    }
+}
+
+/// The data port of the PIC serving the IRQ line and the line's bit in its mask
+fn mask_bit(irq: u8) -> (u16, u8) {
+    assert!(usize::from(irq) < IRQ_LINES, "There is no IRQ line {}", irq);
+
+    if irq < 8 {
+        (PIC_1_DATA_PORT, 1 << irq)
+    } else {
+        (PIC_2_DATA_PORT, 1 << (irq - 8))
+    }
+}
+
+/// Sets the mask bit of the IRQ line, so that the PIC serving it ignores it
+///
+/// The cascade line is only masked once all lines of the slave PIC are.
+/// Panics if there is no such IRQ line.
+pub fn mask_irq(irq: u8) {
+    let (port, bit) = mask_bit(irq);
+
+    critical_section(|| {
+        let mut port: Port<u8> = Port::new(port);
+
+        unsafe {
+            if irq == CASCADE_IRQ && Port::<u8>::new(PIC_2_DATA_PORT).read() != 0xff {
+                return;
+            }
+
+            let mask = port.read();
+            port.write(mask | bit);
+        }
+    });
+}
+
+/// Clears the mask bit of the IRQ line on the PIC serving it
+///
+/// The cascade line is unmasked along with the lines of the slave PIC.
+/// Panics if there is no such IRQ line.
+pub fn unmask_irq(irq: u8) {
+    let (port, bit) = mask_bit(irq);
+
+    critical_section(|| {
+        let mut port: Port<u8> = Port::new(port);
+
+        unsafe {
+            let mask = port.read();
+            port.write(mask & !bit);
+        }
+    });
+
+    if irq >= 8 {
+        unmask_irq(CASCADE_IRQ);
+    }
+}
+
+/// Checks if the mask bit of the IRQ line is set
+///
+/// Panics if there is no such IRQ line.
+pub fn is_irq_masked(irq: u8) -> bool {
+    let (port, bit) = mask_bit(irq);
+    let mask = unsafe { Port::<u8>::new(port).read() };
+
+    mask & bit != 0
+}
+
+/// Checks if an interrupt of the IRQ line was raised spuriously by a PIC
+///
+/// A spurious interrupt is not in service, so it must not be acknowledged:
+/// except that for one of the slave, the master did see a real interrupt
+/// on the cascade line, which is acknowledged here.
+fn is_spurious(irq: u8, ports: &mut impl PortIo) -> bool {
+    let command_port = match irq {
+        MASTER_SPURIOUS_IRQ => PIC_1_COMMAND_PORT,
+        SLAVE_SPURIOUS_IRQ  => PIC_2_COMMAND_PORT,
+        _                   => return false,
+    };
+
+    ports.write_u8(command_port, READ_IN_SERVICE_REGISTER);
+    let in_service = ports.read_u8(command_port) & (1 << 7) != 0;
+    if !in_service && command_port == PIC_2_COMMAND_PORT {
+        ports.write_u8(PIC_1_COMMAND_PORT, END_OF_INTERRUPT);
+    }
+
+    !in_service
+}
+
+fn dispatch(irq: u8, stack_frame: &mut InterruptStackFrame) {
+    if is_spurious(irq, &mut MachinePorts) {
+        return;
+    }
+
+    let handler = HANDLERS.lock()[usize::from(irq)];
+    if let Some(handler) = handler {
+        handler(stack_frame);
+    }
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(PIC_1_OFFSET + irq);
+    }
+}
+
+macro_rules! dispatching_handlers {
+    ($($name:ident => $irq:expr),* $(,)?) => {
+        $(
+            extern "x86-interrupt"
+            fn $name(stack_frame: &mut InterruptStackFrame) {
+                dispatch($irq, stack_frame);
+            }
+        )*
+
+        /// Sets the handler of each dispatched IRQ line to one consulting the dispatch table
+        pub(super) fn set_dispatching_handlers(idt: &mut InterruptDescriptorTable) {
+            $(
+                idt[usize::from(PIC_1_OFFSET + $irq)]
+                   .set_handler_fn($name);
+            )*
+        }
+    };
+}
+
+dispatching_handlers! {
+    irq_3  => 3,
+    irq_4  => 4,
+    irq_5  => 5,
+    irq_6  => 6,
+    irq_7  => 7,
+    irq_8  => 8,
+    irq_9  => 9,
+    irq_10 => 10,
+    irq_11 => 11,
+    irq_12 => 12,
+    irq_13 => 13,
+    irq_14 => 14,
+    irq_15 => 15,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::port_io::RecordingPorts;
+    use core::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    /// Ports of PICs reporting `in_service` as the in-service register of each read
+    fn pics_in_service(in_service: u8) -> RecordingPorts {
+        let mut ports = RecordingPorts::new();
+        ports.queue_reads(PIC_1_COMMAND_PORT, &[in_service]);
+        ports.queue_reads(PIC_2_COMMAND_PORT, &[in_service]);
+
+        ports
+    }
+
+    static SPARE_IRQ_HITS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_spare_irq(_: &mut InterruptStackFrame) {
+        SPARE_IRQ_HITS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test_case]
+    fn registered_irq_handler_is_called() {
+        register_irq_handler(5, count_spare_irq);
+
+        // PIC_1_OFFSET + 5
+        unsafe {
+            asm!("int 37");
+        }
+
+        assert_eq!(SPARE_IRQ_HITS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test_case]
+    fn spurious_master_interrupt_is_not_acknowledged() {
+        let mut ports = pics_in_service(0);
+
+        assert!(is_spurious(7, &mut ports));
+        assert_eq!(ports.reads(0xa0), 0);
+        assert_eq!(ports.writes, [(0x20, 0x0b)]);
+    }
+
+    #[test_case]
+    fn spurious_slave_interrupt_is_acknowledged_only_on_the_master() {
+        let mut ports = pics_in_service(0);
+
+        assert!(is_spurious(15, &mut ports));
+        assert_eq!(ports.reads(0x20), 0);
+        assert_eq!(ports.writes, [(0xa0, 0x0b), (0x20, 0x20)]);
+    }
+
+    #[test_case]
+    fn real_interrupts_are_not_spurious() {
+        let mut ports = pics_in_service(1 << 7);
+
+        assert!(!is_spurious(7, &mut ports));
+        assert!(!is_spurious(15, &mut ports));
+        assert!(!is_spurious(5, &mut ports));
+        assert_eq!((ports.reads(0x20), ports.reads(0xa0)), (1, 1));
+        assert_eq!(ports.writes, [(0x20, 0x0b), (0xa0, 0x0b)]);
+    }
+
+    #[test_case]
+    fn masked_irq_line_is_set_in_the_mask_register() {
+        // The keyboard line
+        mask_irq(1);
+        let mask = unsafe { Port::<u8>::new(PIC_1_DATA_PORT).read() };
+        unmask_irq(1);
+
+        assert_eq!(mask & (1 << 1), 1 << 1);
+        assert!(!is_irq_masked(1));
+    }
+
+    #[test_case]
+    fn cascade_line_stays_unmasked_for_the_slave_lines() {
+        // The PS/2 mouse line
+        let was_masked = is_irq_masked(12);
+        unmask_irq(12);
+        mask_irq(CASCADE_IRQ);
+        let cascade_masked = is_irq_masked(CASCADE_IRQ);
+        if was_masked {
+            mask_irq(12);
+        }
+
+        assert!(!cascade_masked);
+    }
+}
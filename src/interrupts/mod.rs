@@ -1,4 +1,10 @@
+pub mod apic;
+mod fallback;
+mod guard;
 mod interrupt_index;
+mod irq;
+pub mod pit;
+pub mod syscall;
 
 use x86_64::structures::idt::{
     InterruptDescriptorTable,
@@ -6,11 +12,23 @@ use x86_64::structures::idt::{
     PageFaultErrorCode,
 };
 use lazy_static::lazy_static;
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
 use crate::{
     println,
     print,
     gdt,
     hlt_loop,
+    keyboard,
+    sched,
+    serial,
+    time,
+    vga_buffer,
 };
 use pic8259_simple::{
     ChainedPics,
@@ -19,9 +37,27 @@ use interrupt_index::{
     InterruptIndex,
 };
 
+pub use guard::{
+    critical_section,
+    InterruptGuard,
+};
+pub use irq::{
+    register_irq_handler,
+    mask_irq,
+    unmask_irq,
+    is_irq_masked,
+    IrqHandler,
+};
+
 const PIC_1_OFFSET: u8 = 32;
 const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+/// The frequency of the timer interrupt set during initialisation
+pub const DEFAULT_TIMER_HZ: u32 = 100;
+
+/// Whether the timer interrupt handler prints a dot on each tick
+static TIMER_VERBOSE: AtomicBool = AtomicBool::new(false);
+
 static PICS: spin::Mutex<ChainedPics> = spin::Mutex::new(unsafe { 
             ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) 
         });
@@ -33,22 +69,106 @@ pub fn init_pics() {
     }
 }
 
+/// The controllers the hardware interrupts are taken from, chosen at boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptController {
+    /// The chained 8259 PICs
+    Pic,
+    /// The Local APIC and the IO-APIC, or the PICs if the CPU has no Local APIC
+    Apic,
+}
+
+/// Routes the timer and keyboard interrupts through the APICs instead of the PICs
+///
+/// May be called once after `init_pics`. Returns false and keeps using
+/// the PICs if the CPU has no Local APIC.
+pub fn init_apic() -> bool {
+    if !apic::is_present() {
+        return false;
+    }
+
+    critical_section(|| unsafe {
+        apic::enable();
+    });
+
+    true
+}
+
+/// Signals the end of the interrupt with the given index to the controller it came from
+fn end_of_interrupt(index: InterruptIndex) {
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe {
+            PICS.lock()
+                .notify_end_of_interrupt(index.as_u8());
+        }
+    }
+}
+
+/// Programs the PIT to fire the timer interrupt `hz` times per second
+///
+/// Frequencies which cannot be represented by the PIT's 16-bit divisor are clamped.
+pub fn init_timer(hz: u32) {
+    let divisor = pit::divisor(hz);
+
+    critical_section(|| {
+        pit::set_divisor(divisor);
+        time::set_timer_divisor(divisor);
+    });
+}
+
+/// Makes the timer interrupt handler print a dot on each tick, or stop doing so
+///
+/// The handler is silent by default.
+pub fn set_timer_verbose(verbose: bool) {
+    TIMER_VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Enables or disables the timer interrupt by unmasking or masking its IRQ line
+///
+/// While it is disabled, the tick counter and the scheduler stand still.
+pub fn set_timer_output(enabled: bool) {
+    let irq = InterruptIndex::Timer.as_u8() - PIC_1_OFFSET;
+
+    if enabled {
+        unmask_irq(irq);
+    } else {
+        mask_irq(irq);
+    }
+}
+
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
 
+        fallback::set_fallback_handlers(&mut idt);
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.non_maskable_interrupt.set_handler_fn(non_maskable_interrupt_handler);
         idt.breakpoint.set_handler_fn(breakpoint_handler);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
         unsafe {
             idt.double_fault
                .set_handler_fn(double_fault_handler)
                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.machine_check
+               .set_handler_fn(machine_check_handler)
+               .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+            idt.page_fault
+               .set_handler_fn(page_fault_handler)
+               .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
         }
-        idt.page_fault.set_handler_fn(page_fault_handler);
+        idt.general_protection_fault
+           .set_handler_fn(general_protection_fault_handler);
 
+        irq::set_dispatching_handlers(&mut idt);
+        syscall::set_syscall_handler(&mut idt);
         idt[InterruptIndex::Timer.as_usize()]
            .set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()]
            .set_handler_fn(keyboard_interrupt_handler);
+        idt[usize::from(apic::SPURIOUS_VECTOR)]
+           .set_handler_fn(apic::spurious_interrupt_handler);
 
         idt
     };
@@ -59,59 +179,77 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// Reports an exception which cannot be masked, through serial or else the screen
+///
+/// It may interrupt a print, so whichever of them is locked is skipped instead of waited for.
+fn report_unmaskable(args: fmt::Arguments) {
+    use fmt::Write;
+
+    if serial::try_print(args) {
+        return;
+    }
+    if let Some(mut writer) = vga_buffer::WRITER.try_lock() {
+        let _ = writer.write_fmt(args);
+        writer.flush();
+    }
+}
+
+/// Reports the NMI and continues, since they can be spurious in virtual machines
+extern "x86-interrupt"
+fn non_maskable_interrupt_handler(stack_frame: &mut InterruptStackFrame) {
+    report_unmaskable(format_args!("EXCEPTION: NON-MASKABLE INTERRUPT\n{:#?}\n", stack_frame));
+}
+
 extern "x86-interrupt"
 fn breakpoint_handler(stack_frame: &mut InterruptStackFrame) {
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
+extern "x86-interrupt"
+fn divide_error_handler(stack_frame: &mut InterruptStackFrame) {
+    println!("EXCEPTION: DIVIDE ERROR\n{:#?}", stack_frame);
+    hlt_loop();
+}
+
+extern "x86-interrupt"
+fn invalid_opcode_handler(stack_frame: &mut InterruptStackFrame) {
+    println!("EXCEPTION: INVALID OPCODE\n{:#?}", stack_frame);
+    hlt_loop();
+}
+
 extern "x86-interrupt"
 fn double_fault_handler(stack_frame: &mut InterruptStackFrame, _error_code: u64) -> ! {
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
+extern "x86-interrupt"
+fn machine_check_handler(stack_frame: &mut InterruptStackFrame) -> ! {
+    report_unmaskable(format_args!("EXCEPTION: MACHINE CHECK\n{:#?}\n", stack_frame));
+    hlt_loop();
+}
+
 extern "x86-interrupt" 
 fn timer_interrupt_handler(_: &mut InterruptStackFrame) {
-    print!(".");
-
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+    time::tick();
+    if TIMER_VERBOSE.load(Ordering::Relaxed) {
+        print!(".");
     }
+    crate::check_test_timeout();
+
+    end_of_interrupt(InterruptIndex::Timer);
+
+    sched::schedule();
 }
 
 extern "x86-interrupt"
 fn keyboard_interrupt_handler(_: &mut InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
     use x86_64::instructions::port::Port;
 
-    lazy_static! {
-        static ref KEYBOARD: spin::Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> =
-            spin::Mutex::new(
-                Keyboard::new(
-                    layouts::Us104Key,
-                    ScancodeSet1,
-                    HandleControl::Ignore,
-                )
-            );
-    }
-
-    let mut keyboard = KEYBOARD.lock();
-    let mut ps2_data_port = Port::new(0x60);
-
+    let mut ps2_data_port = Port::new(crate::ps2::DATA_PORT);
     let scancode: u8 = unsafe { ps2_data_port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(c) => print!("{}", c),
-                DecodedKey::RawKey(k)  => print!("{:?}", k),
-            }
-        }
-    }
+    keyboard::add_scancode(scancode);
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
-    }
+    end_of_interrupt(InterruptIndex::Keyboard);
 }
 
 extern "x86-interrupt"
@@ -125,10 +263,38 @@ fn page_fault_handler(stack_frame: &mut InterruptStackFrame, error_code: PageFau
     hlt_loop();
 }
 
+extern "x86-interrupt"
+fn general_protection_fault_handler(stack_frame: &mut InterruptStackFrame, error_code: u64) {
+    println!("EXCEPTION: GENERAL PROTECTION FAULT");
+    println!("Selector Error Code: {:#x}", error_code);
+    println!("{:#?}", stack_frame);
+    hlt_loop();
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::{
+        time,
+        vga_buffer,
+    };
+
     #[test_case]
     fn breakpoint_exception_is_handled() {
         x86_64::instructions::interrupts::int3();
     }
-}
\ No newline at end of file
+
+    #[test_case]
+    fn non_maskable_interrupt_is_handled() {
+        unsafe {
+            asm!("int 2");
+        }
+    }
+
+    #[test_case]
+    fn silent_timer_leaves_the_screen_unchanged() {
+        let before = vga_buffer::screen_snapshot();
+        time::sleep_ms(time::ticks_to_ms(5));
+
+        assert!(vga_buffer::screen_snapshot() == before);
+    }
+}
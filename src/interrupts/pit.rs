@@ -0,0 +1,59 @@
+use x86_64::instructions::port::Port;
+
+/// The frequency of the PIT's oscillator in Hz
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL_0_PORT: u16 = 0x40;
+const COMMAND_PORT: u16 = 0x43;
+
+/// Selects channel 0, lobyte/hibyte access, mode 3 (square wave generator) and binary mode
+const CHANNEL_0_SQUARE_WAVE: u8 = 0b0011_0110;
+
+/// Computes the divisor that makes channel 0 fire `hz` times per second.
+///
+/// The result is clamped to the range of the 16-bit reload register,
+/// so too low frequencies select the slowest and too high ones the fastest rate.
+pub fn divisor(hz: u32) -> u16 {
+    let divisor = BASE_FREQUENCY / hz.max(1);
+
+    divisor.max(1)
+           .min(u32::from(u16::MAX)) as u16
+}
+
+/// Programs channel 0 with the given divisor.
+pub fn set_divisor(divisor: u16) {
+    for &(port, value) in write_sequence(divisor).iter() {
+        unsafe {
+            Port::new(port).write(value);
+        }
+    }
+}
+
+/// The (port, value) writes which program channel 0 with `divisor`.
+fn write_sequence(divisor: u16) -> [(u16, u8); 3] {
+    [
+        (COMMAND_PORT, CHANNEL_0_SQUARE_WAVE),
+        (CHANNEL_0_PORT, divisor as u8),
+        (CHANNEL_0_PORT, (divisor >> 8) as u8),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn divisor_for_1000_hz_is_written_low_byte_first() {
+        assert_eq!(
+            write_sequence(divisor(1000)),
+            [(0x43, 0x36), (0x40, 0xa9), (0x40, 0x04)]
+        );
+    }
+
+    #[test_case]
+    fn divisor_is_clamped_to_u16() {
+        assert_eq!(divisor(0), u16::MAX);
+        assert_eq!(divisor(1), u16::MAX);
+        assert_eq!(divisor(2 * BASE_FREQUENCY), 1);
+    }
+}
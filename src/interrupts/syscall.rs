@@ -0,0 +1,148 @@
+use x86_64::{
+    PrivilegeLevel,
+    structures::idt::{
+        HandlerFunc,
+        InterruptDescriptorTable,
+    },
+};
+use crate::{
+    print,
+    println,
+    hlt_loop,
+};
+
+/// The interrupt vector of the syscall entry point
+pub const SYSCALL_VECTOR: usize = 0x80;
+
+/// `write(ptr, len)` prints `len` UTF-8 bytes at `ptr` and returns `len`
+pub const WRITE: u64 = 0;
+
+/// `exit(code)` terminates the caller and never returns
+pub const EXIT: u64 = 1;
+
+/// The result of a syscall with an unknown number or invalid arguments
+pub const ERROR: u64 = u64::MAX;
+
+type SyscallHandler = fn(u64, u64, u64) -> u64;
+
+/// The syscall handlers indexed by syscall number
+const SYSCALLS: [SyscallHandler; 2] = [
+    sys_write,
+    sys_exit,
+];
+
+// The x86-interrupt ABI does not expose the general purpose registers,
+// so the entry point is a plain stub which preserves the caller-saved registers,
+// moves rax/rdi/rsi/rdx into the System V argument registers
+// and returns the dispatcher's result in rax.
+global_asm!(r#"
+.intel_syntax noprefix
+.global syscall_entry
+syscall_entry:
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push r8
+    push r9
+    push r10
+    push r11
+    sub rsp, 8
+    cld
+    mov rcx, rdx
+    mov rdx, rsi
+    mov rsi, rdi
+    mov rdi, rax
+    call syscall_dispatch
+    add rsp, 8
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    iretq
+.att_syntax
+"#);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// Installs the syscall entry point, invokable from ring 3
+pub(super) fn set_syscall_handler(idt: &mut InterruptDescriptorTable) {
+    // The stub is entered like any interrupt handler,
+    // it just saves the registers on its own.
+    let entry: HandlerFunc = unsafe {
+        core::mem::transmute(syscall_entry as unsafe extern "C" fn())
+    };
+
+    idt[SYSCALL_VECTOR]
+       .set_handler_fn(entry)
+       .set_privilege_level(PrivilegeLevel::Ring3);
+}
+
+#[no_mangle]
+extern "C" fn syscall_dispatch(number: u64, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+    match SYSCALLS.get(number as usize) {
+        Some(handler) => handler(arg0, arg1, arg2),
+        None          => ERROR,
+    }
+}
+
+fn sys_write(ptr: u64, len: u64, _: u64) -> u64 {
+    if ptr == 0 {
+        return ERROR;
+    }
+
+    let bytes = unsafe {
+        core::slice::from_raw_parts(ptr as *const u8, len as usize)
+    };
+    match core::str::from_utf8(bytes) {
+        Ok(text) => {
+            print!("{}", text);
+            len
+        },
+        Err(_) => ERROR,
+    }
+}
+
+fn sys_exit(code: u64, _: u64, _: u64) -> u64 {
+    println!("task exited with code {}", code);
+    hlt_loop()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vga_buffer;
+
+    fn syscall(number: u64, arg0: u64, arg1: u64) -> u64 {
+        let result: u64;
+        unsafe {
+            asm!("int 0x80",
+                 inlateout("rax") number => result,
+                 in("rdi") arg0,
+                 in("rsi") arg1,
+                 in("rdx") 0);
+        }
+
+        result
+    }
+
+    #[test_case]
+    fn write_syscall_prints_the_bytes() {
+        let text = "written by int 0x80";
+        let written = syscall(WRITE, text.as_ptr() as u64, text.len() as u64);
+
+        assert_eq!(written, text.len() as u64);
+        assert!(vga_buffer::screen_contains(text));
+    }
+
+    #[test_case]
+    fn unknown_syscall_returns_an_error() {
+        assert_eq!(syscall(42, 0, 0), ERROR);
+    }
+}
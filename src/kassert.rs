@@ -0,0 +1,99 @@
+use core::fmt;
+use crate::serial_println;
+
+/// Asserts that the condition is true, like `assert!`
+///
+/// On failure the location, the condition and the optional message are printed
+/// to serial before panicking, so that they show up in the test output.
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::kassert::_failed(file!(), line!(), stringify!($cond), None);
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::kassert::_failed(file!(), line!(), stringify!($cond), Some(format_args!($($arg)+)));
+        }
+    };
+}
+
+/// Asserts that the two expressions are equal, like `assert_eq!`
+///
+/// On failure the location, both expressions and their `Debug` values and the optional message
+/// are printed to serial before panicking, so that they show up in the test output.
+#[macro_export]
+macro_rules! kassert_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    $crate::kassert::_failed_eq(file!(), line!(), stringify!($left), stringify!($right), left, right, None);
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left != *right {
+                    $crate::kassert::_failed_eq(
+                        file!(),
+                        line!(),
+                        stringify!($left),
+                        stringify!($right),
+                        left,
+                        right,
+                        Some(format_args!($($arg)+)),
+                    );
+                }
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn _failed(file: &str, line: u32, cond: &str, message: Option<fmt::Arguments>) -> ! {
+    serial_println!("\nassertion failed at {}:{}: `{}`", file, line, cond);
+    print_message(message);
+
+    panic!("assertion failed: `{}`", cond);
+}
+
+#[doc(hidden)]
+pub fn _failed_eq(
+    file: &str,
+    line: u32,
+    left_expr: &str,
+    right_expr: &str,
+    left: &dyn fmt::Debug,
+    right: &dyn fmt::Debug,
+    message: Option<fmt::Arguments>,
+) -> ! {
+    serial_println!("\nassertion failed at {}:{}: `{} == {}`", file, line, left_expr, right_expr);
+    serial_println!("  left: `{:?}`", left);
+    serial_println!(" right: `{:?}`", right);
+    print_message(message);
+
+    panic!("assertion failed: `{} == {}`", left_expr, right_expr);
+}
+
+fn print_message(message: Option<fmt::Arguments>) {
+    if let Some(message) = message {
+        serial_println!("  note: {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test_case]
+    fn passing_assertions_do_not_panic() {
+        let answer = 6 * 7;
+
+        crate::kassert!(answer > 40);
+        crate::kassert!(answer % 2 == 0, "{} is odd", answer);
+        crate::kassert_eq!(answer, 42);
+        crate::kassert_eq!([1, 2], [1, 2], "arrays differ");
+    }
+}
@@ -0,0 +1,721 @@
+use pc_keyboard::{
+    layouts,
+    DecodedKey,
+    Error,
+    HandleControl,
+    KeyCode,
+    KeyEvent,
+    KeyState,
+    Keyboard,
+    ScancodeSet,
+    ScancodeSet1,
+    ScancodeSet2,
+};
+use core::{
+    pin::Pin,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    task::{
+        Context,
+        Poll,
+    },
+};
+use futures_util::{
+    stream::{
+        Stream,
+        StreamExt,
+    },
+    task::AtomicWaker,
+};
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use crate::{
+    print,
+    println,
+    input,
+    power,
+    util::RingBuffer,
+    vga_buffer,
+};
+
+/// The maximum number of scancodes waiting to be decoded
+const SCANCODE_QUEUE_CAPACITY: usize = 128;
+
+/// The scancodes pushed by `add_scancode` and popped by `pop_scancode`
+static SCANCODE_QUEUE: RingBuffer<u8, SCANCODE_QUEUE_CAPACITY> = RingBuffer::new();
+
+static DROPPED_SCANCODE_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Wakes the task waiting on a `ScancodeStream`
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// The keyboard layouts which can be selected at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    Us104,
+    /// The US layout where ` ' ^ ~ and " are dead keys, combining with the letter typed next
+    UsInternational,
+    Uk105,
+    Dvorak104,
+}
+
+impl KeyboardLayout {
+    fn has_dead_keys(self) -> bool {
+        self == KeyboardLayout::UsInternational
+    }
+}
+
+/// The scancode sets which can be selected at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardScancodeSet {
+    /// The set the PS/2 controller translates to by default
+    Set1,
+    Set2,
+}
+
+/// A keyboard decoder for any of the supported layouts, reading scancodes of the set `S`
+enum LayoutKeyboard<S: ScancodeSet> {
+    Us104(Keyboard<layouts::Us104Key, S>),
+    Uk105(Keyboard<layouts::Uk105Key, S>),
+    Dvorak104(Keyboard<layouts::Dvorak104Key, S>),
+}
+
+impl<S: ScancodeSet> LayoutKeyboard<S> {
+    fn new(layout: KeyboardLayout, scancode_set: S) -> Self {
+        match layout {
+            KeyboardLayout::Us104 | KeyboardLayout::UsInternational => LayoutKeyboard::Us104(
+                Keyboard::new(layouts::Us104Key, scancode_set, HandleControl::Ignore)
+            ),
+            KeyboardLayout::Uk105 => LayoutKeyboard::Uk105(
+                Keyboard::new(layouts::Uk105Key, scancode_set, HandleControl::Ignore)
+            ),
+            KeyboardLayout::Dvorak104 => LayoutKeyboard::Dvorak104(
+                Keyboard::new(layouts::Dvorak104Key, scancode_set, HandleControl::Ignore)
+            ),
+        }
+    }
+
+    fn add_byte(&mut self, scancode: u8) -> Result<Option<KeyEvent>, Error> {
+        match self {
+            LayoutKeyboard::Us104(keyboard)     => keyboard.add_byte(scancode),
+            LayoutKeyboard::Uk105(keyboard)     => keyboard.add_byte(scancode),
+            LayoutKeyboard::Dvorak104(keyboard) => keyboard.add_byte(scancode),
+        }
+    }
+
+    fn process_keyevent(&mut self, key_event: KeyEvent) -> Option<DecodedKey> {
+        match self {
+            LayoutKeyboard::Us104(keyboard)     => keyboard.process_keyevent(key_event),
+            LayoutKeyboard::Uk105(keyboard)     => keyboard.process_keyevent(key_event),
+            LayoutKeyboard::Dvorak104(keyboard) => keyboard.process_keyevent(key_event),
+        }
+    }
+}
+
+/// A keyboard decoder for any of the supported layouts and scancode sets
+enum ScancodeKeyboard {
+    Set1(LayoutKeyboard<ScancodeSet1>),
+    Set2(LayoutKeyboard<ScancodeSet2>),
+}
+
+impl ScancodeKeyboard {
+    fn new(layout: KeyboardLayout, scancode_set: KeyboardScancodeSet) -> Self {
+        match scancode_set {
+            KeyboardScancodeSet::Set1 => ScancodeKeyboard::Set1(LayoutKeyboard::new(layout, ScancodeSet1)),
+            KeyboardScancodeSet::Set2 => ScancodeKeyboard::Set2(LayoutKeyboard::new(layout, ScancodeSet2)),
+        }
+    }
+
+    fn add_byte(&mut self, scancode: u8) -> Result<Option<KeyEvent>, Error> {
+        match self {
+            ScancodeKeyboard::Set1(keyboard) => keyboard.add_byte(scancode),
+            ScancodeKeyboard::Set2(keyboard) => keyboard.add_byte(scancode),
+        }
+    }
+
+    fn process_keyevent(&mut self, key_event: KeyEvent) -> Option<DecodedKey> {
+        match self {
+            ScancodeKeyboard::Set1(keyboard) => keyboard.process_keyevent(key_event),
+            ScancodeKeyboard::Set2(keyboard) => keyboard.process_keyevent(key_event),
+        }
+    }
+}
+
+const ESCAPE: char = '\u{1b}';
+
+/// The characters of the dead keys, each followed by the letters it combines with and their combinations
+const COMPOSITIONS: [(char, &str, &str); 5] = [
+    ('`',  "aeiouAEIOU",     "àèìòùÀÈÌÒÙ"),
+    ('\'', "aeiouycAEIOUYC", "áéíóúýçÁÉÍÓÚÝÇ"),
+    ('^',  "aeiouAEIOU",     "âêîôûÂÊÎÔÛ"),
+    ('~',  "anoANO",         "ãñõÃÑÕ"),
+    ('"',  "aeiouyAEIOU",    "äëïöüÿÄËÏÖÜ"),
+];
+
+fn is_dead_key(c: char) -> bool {
+    COMPOSITIONS.iter().any(|&(dead_key, _, _)| dead_key == c)
+}
+
+/// Returns the combination of the diacritic of the dead key with the letter, if there is one
+fn composed(dead_key: char, letter: char) -> Option<char> {
+    let &(_, letters, combinations) = COMPOSITIONS.iter().find(|&&(key, _, _)| key == dead_key)?;
+    let index = letters.chars().position(|c| c == letter)?;
+
+    combinations.chars().nth(index)
+}
+
+/// Combines the characters of dead keys with the letters typed after them
+#[derive(Default)]
+struct Composer {
+    /// The dead key typed last, waiting for the next character
+    pending: Option<char>,
+    /// A key which has to be returned after the one returned last
+    queued: Option<DecodedKey>,
+}
+
+impl Composer {
+    /// Returns the key to emit for the decoded one, if any
+    ///
+    /// A dead key emits nothing until the next character: a letter it combines with
+    /// emits the combination, a space or the dead key again emits its character, Escape emits
+    /// nothing and any other character emits the character of the dead key followed by itself.
+    /// Keys without a character are emitted as they are.
+    fn compose(&mut self, key: DecodedKey) -> Option<DecodedKey> {
+        let c = match key {
+            DecodedKey::Unicode(c) => c,
+            DecodedKey::RawKey(_)  => return Some(key),
+        };
+
+        match self.pending.take() {
+            None if is_dead_key(c) => {
+                self.pending = Some(c);
+                None
+            },
+            None                                        => Some(key),
+            Some(_) if c == ESCAPE                      => None,
+            Some(dead_key) if c == ' ' || c == dead_key => Some(DecodedKey::Unicode(dead_key)),
+            Some(dead_key) => match composed(dead_key, c) {
+                Some(combination) => Some(DecodedKey::Unicode(combination)),
+                None              => {
+                    self.queued = Some(key);
+                    Some(DecodedKey::Unicode(dead_key))
+                },
+            },
+        }
+    }
+}
+
+/// The keyboard decoder along with the layout and the scancode set it was created for
+struct KeyboardDecoder {
+    layout: KeyboardLayout,
+    scancode_set: KeyboardScancodeSet,
+    keyboard: ScancodeKeyboard,
+    composer: Composer,
+}
+
+impl KeyboardDecoder {
+    fn new(layout: KeyboardLayout, scancode_set: KeyboardScancodeSet) -> Self {
+        KeyboardDecoder {
+            layout,
+            scancode_set,
+            keyboard: ScancodeKeyboard::new(layout, scancode_set),
+            composer: Composer::default(),
+        }
+    }
+
+    /// Returns the key to emit for the decoded one, combining dead keys if the layout has them
+    fn compose(&mut self, key: DecodedKey) -> Option<DecodedKey> {
+        if self.layout.has_dead_keys() {
+            self.composer.compose(key)
+        } else {
+            Some(key)
+        }
+    }
+}
+
+lazy_static! {
+    static ref KEYBOARD: Mutex<KeyboardDecoder> = Mutex::new(
+        KeyboardDecoder::new(KeyboardLayout::Us104, KeyboardScancodeSet::Set1)
+    );
+}
+
+/// The state of the modifier keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModifierState {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+/// Tracks the modifiers on both sides of the keyboard
+struct Modifiers {
+    left_shift: bool,
+    right_shift: bool,
+    left_ctrl: bool,
+    right_ctrl: bool,
+    left_alt: bool,
+    right_alt: bool,
+    caps_lock: bool,
+    num_lock: bool,
+    scroll_lock: bool,
+}
+
+impl Modifiers {
+    const fn new() -> Self {
+        Modifiers {
+            left_shift: false,
+            right_shift: false,
+            left_ctrl: false,
+            right_ctrl: false,
+            left_alt: false,
+            right_alt: false,
+            caps_lock: false,
+            num_lock: false,
+            scroll_lock: false,
+        }
+    }
+
+    /// Sets or clears the flag of the modifier the key event is for, if any
+    ///
+    /// The lock keys are toggled each time they are pressed.
+    /// Returns true if a lock key was toggled.
+    fn update(&mut self, key_event: &KeyEvent) -> bool {
+        let is_down = key_event.state == KeyState::Down;
+
+        match key_event.code {
+            KeyCode::ShiftLeft    => self.left_shift = is_down,
+            KeyCode::ShiftRight   => self.right_shift = is_down,
+            KeyCode::ControlLeft  => self.left_ctrl = is_down,
+            KeyCode::ControlRight => self.right_ctrl = is_down,
+            KeyCode::AltLeft      => self.left_alt = is_down,
+            KeyCode::AltRight     => self.right_alt = is_down,
+            KeyCode::CapsLock   if is_down => self.caps_lock = !self.caps_lock,
+            KeyCode::NumpadLock if is_down => self.num_lock = !self.num_lock,
+            KeyCode::ScrollLock if is_down => self.scroll_lock = !self.scroll_lock,
+            _ => return false,
+        }
+
+        match key_event.code {
+            KeyCode::CapsLock | KeyCode::NumpadLock | KeyCode::ScrollLock => is_down,
+            _                                                             => false,
+        }
+    }
+
+    fn state(&self) -> ModifierState {
+        ModifierState {
+            shift: self.left_shift || self.right_shift,
+            ctrl: self.left_ctrl || self.right_ctrl,
+            alt: self.left_alt || self.right_alt,
+            caps_lock: self.caps_lock,
+            num_lock: self.num_lock,
+            scroll_lock: self.scroll_lock,
+        }
+    }
+}
+
+static MODIFIERS: Mutex<Modifiers> = Mutex::new(Modifiers::new());
+
+/// Returns the state of the modifier keys as of the last decoded scancode
+pub fn modifiers() -> ModifierState {
+    MODIFIERS.lock().state()
+}
+
+/// The events raised by control chords
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Raised by Ctrl+C to ask the running command to stop
+    Interrupt,
+}
+
+/// A function called with each raised signal
+pub type SignalHandler = fn(Signal);
+
+static SIGNAL_HANDLERS: Mutex<Vec<SignalHandler>> = Mutex::new(Vec::new());
+
+/// Registers a handler to be called with the signals raised from now on
+pub fn register_signal_handler(handler: SignalHandler) {
+    SIGNAL_HANDLERS.lock().push(handler);
+}
+
+/// Calls the registered handlers with the signal
+///
+/// The handlers are not locked while each of them runs, so that it can register handlers.
+fn raise(signal: Signal) {
+    let handler_at = |i: usize| SIGNAL_HANDLERS.lock().get(i).copied();
+
+    let mut i = 0;
+    while let Some(handler) = handler_at(i) {
+        handler(signal);
+        i += 1;
+    }
+}
+
+/// What pressing a chord does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChordAction {
+    Reboot,
+    Raise(Signal),
+}
+
+/// A key pressed while exactly the given Ctrl and Alt modifiers are held down
+struct Chord {
+    ctrl: bool,
+    alt: bool,
+    key: KeyCode,
+    action: ChordAction,
+}
+
+/// The key combinations handled by the keyboard driver instead of being decoded to keys
+const CHORDS: [Chord; 2] = [
+    Chord { ctrl: true, alt: true,  key: KeyCode::Delete, action: ChordAction::Reboot },
+    Chord { ctrl: true, alt: false, key: KeyCode::C,      action: ChordAction::Raise(Signal::Interrupt) },
+];
+
+/// Returns the action of the chord completed by the key event, if any
+fn chord_action(key_event: &KeyEvent, modifiers: ModifierState) -> Option<ChordAction> {
+    if key_event.state != KeyState::Down {
+        return None;
+    }
+
+    CHORDS.iter()
+          .find(|chord| chord.key == key_event.code && chord.ctrl == modifiers.ctrl && chord.alt == modifiers.alt)
+          .map(|chord| chord.action)
+}
+
+fn run_chord_action(action: ChordAction) {
+    match action {
+        ChordAction::Reboot         => power::reboot(),
+        ChordAction::Raise(signal) => raise(signal),
+    }
+}
+
+/// Selects the layout used to decode scancodes
+///
+/// The decoding state of the previous layout, like pressed modifiers, is discarded.
+pub fn set_layout(layout: KeyboardLayout) {
+    let mut keyboard = KEYBOARD.lock();
+
+    *keyboard = KeyboardDecoder::new(layout, keyboard.scancode_set);
+}
+
+/// Selects the scancode set the keyboard is expected to send
+///
+/// The decoding state of the previous set, like a partly received scancode, is discarded.
+pub fn set_scancode_set(scancode_set: KeyboardScancodeSet) {
+    let mut keyboard = KEYBOARD.lock();
+
+    *keyboard = KeyboardDecoder::new(keyboard.layout, scancode_set);
+}
+
+/// Queues a scancode for decoding by `poll_key`
+///
+/// Called by the keyboard interrupt handler, so it must not block.
+/// If the queue is full the scancode is dropped. Interrupts are disabled meanwhile,
+/// so that the handler cannot push at the same time as other callers.
+pub(crate) fn add_scancode(scancode: u8) {
+    use crate::interrupts::critical_section;
+
+    if critical_section(|| SCANCODE_QUEUE.try_push(scancode)).is_err() {
+        if !DROPPED_SCANCODE_REPORTED.swap(true, Ordering::Relaxed) {
+            println!("WARNING: scancode queue full; dropping keyboard input");
+        }
+    } else {
+        WAKER.wake();
+    }
+}
+
+/// Only called outside of interrupt handlers, so there is a single consumer of the queue
+fn pop_scancode() -> Option<u8> {
+    SCANCODE_QUEUE.try_pop()
+}
+
+/// Decodes the queued scancodes until a key is produced
+///
+/// Returns None if the queued scancodes do not complete a key press.
+pub fn poll_key() -> Option<DecodedKey> {
+    if let Some(key) = queued_key() {
+        return Some(key);
+    }
+
+    while let Some(scancode) = pop_scancode() {
+        if let Some(key) = decode(scancode) {
+            return Some(key);
+        }
+    }
+
+    None
+}
+
+/// Returns the key emitted along with the one decoded last, if any, see `Composer::compose`
+fn queued_key() -> Option<DecodedKey> {
+    KEYBOARD.lock().composer.queued.take()
+}
+
+/// Feeds a scancode to the keyboard decoder, returning the key it completes, if any
+///
+/// The keyboard LEDs are updated when a lock key is toggled.
+/// The keys completing one of the `CHORDS` run its action instead of being returned.
+/// With a layout with dead keys, the key may be combined with the next one or followed
+/// by a `queued_key`.
+fn decode(scancode: u8) -> Option<DecodedKey> {
+    let mut decoder = KEYBOARD.lock();
+
+    if let Ok(Some(key_event)) = decoder.keyboard.add_byte(scancode) {
+        let mut modifiers = MODIFIERS.lock();
+        let lock_toggled = modifiers.update(&key_event);
+        let state = modifiers.state();
+        drop(modifiers);
+
+        let chord_action = chord_action(&key_event, state);
+        let key = decoder.keyboard
+                         .process_keyevent(key_event)
+                         .filter(|_| chord_action.is_none())
+                         .and_then(|key| decoder.compose(key));
+        drop(decoder);
+
+        if lock_toggled {
+            // The LEDs are only cosmetic, the lock state is tracked regardless
+            let _ = input::set_leds(state.caps_lock, state.num_lock, state.scroll_lock);
+        }
+
+        if let Some(action) = chord_action {
+            run_chord_action(action);
+            return None;
+        }
+
+        key
+    } else {
+        None
+    }
+}
+
+/// An asynchronous stream of the scancodes received from the keyboard
+///
+/// It shares the scancode queue with `poll_key`, so only one of them should be used.
+pub struct ScancodeStream {
+    _private: (),
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        ScancodeStream {
+            _private: (),
+        }
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<u8>> {
+        if let Some(scancode) = pop_scancode() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        WAKER.register(context.waker());
+        match pop_scancode() {
+            Some(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Prints the keys pressed on the keyboard
+pub async fn print_keypresses() {
+    let mut scancodes = ScancodeStream::new();
+
+    while let Some(scancode) = scancodes.next().await {
+        if let Some(key) = decode(scancode) {
+            handle_key(key);
+        }
+        if let Some(key) = queued_key() {
+            handle_key(key);
+        }
+    }
+}
+
+/// Prints the character of a typed key, or scrolls the screen for the page and arrow keys
+///
+/// Other keys without a character are ignored.
+fn handle_key(key: DecodedKey) {
+    let page = || vga_buffer::dimensions().1 - 1;
+
+    match key {
+        DecodedKey::Unicode(c)                 => print!("{}", c),
+        DecodedKey::RawKey(KeyCode::PageUp)    => vga_buffer::scroll_up(page()),
+        DecodedKey::RawKey(KeyCode::PageDown)  => vga_buffer::scroll_down(page()),
+        DecodedKey::RawKey(KeyCode::ArrowUp)   => vga_buffer::scroll_up(1),
+        DecodedKey::RawKey(KeyCode::ArrowDown) => vga_buffer::scroll_down(1),
+        DecodedKey::RawKey(_)                  => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn poll_key_decodes_queued_scancodes() {
+        let a_pressed = 0x1e;
+        let a_released = 0x9e;
+
+        add_scancode(a_pressed);
+        add_scancode(a_released);
+
+        assert_eq!(poll_key(), Some(DecodedKey::Unicode('a')));
+        assert_eq!(poll_key(), None);
+    }
+
+    #[test_case]
+    fn scancodes_are_decoded_with_the_selected_layout() {
+        let s_pressed = 0x1f;
+        let s_released = 0x9f;
+
+        set_layout(KeyboardLayout::Dvorak104);
+        add_scancode(s_pressed);
+        add_scancode(s_released);
+        let key = poll_key();
+        set_layout(KeyboardLayout::Us104);
+
+        assert_eq!(key, Some(DecodedKey::Unicode('o')));
+    }
+
+    #[test_case]
+    fn scancodes_are_decoded_with_the_selected_scancode_set() {
+        let a_pressed = 0x1c;
+        let a_released = [0xf0, 0x1c];
+
+        set_scancode_set(KeyboardScancodeSet::Set2);
+        add_scancode(a_pressed);
+        add_scancode(a_released[0]);
+        add_scancode(a_released[1]);
+        let key = poll_key();
+        let release = poll_key();
+        set_scancode_set(KeyboardScancodeSet::Set1);
+
+        assert_eq!(key, Some(DecodedKey::Unicode('a')));
+        assert_eq!(release, None);
+    }
+
+    #[test_case]
+    fn dead_keys_combine_with_the_next_letter() {
+        let apostrophe = [0x28, 0xa8];
+        let e = [0x12, 0x92];
+        let x = [0x2d, 0xad];
+        fn keys_after(scancodes: &[u8]) -> (Option<DecodedKey>, Option<DecodedKey>) {
+            for &scancode in scancodes {
+                add_scancode(scancode);
+            }
+            (poll_key(), poll_key())
+        }
+
+        set_layout(KeyboardLayout::UsInternational);
+        let dead_key = keys_after(&apostrophe);
+        let combined = keys_after(&e);
+        keys_after(&apostrophe);
+        let uncombined = keys_after(&x);
+        set_layout(KeyboardLayout::Us104);
+
+        assert_eq!(dead_key, (None, None));
+        assert_eq!(combined, (Some(DecodedKey::Unicode('é')), None));
+        assert_eq!(uncombined, (Some(DecodedKey::Unicode('\'')), Some(DecodedKey::Unicode('x'))));
+    }
+
+    #[test_case]
+    fn modifiers_are_tracked_while_decoding() {
+        let shift_pressed = 0x2a;
+        let shift_released = 0xaa;
+        let a_pressed = 0x1e;
+        let a_released = 0x9e;
+
+        add_scancode(shift_pressed);
+        add_scancode(a_pressed);
+        assert_eq!(poll_key(), Some(DecodedKey::Unicode('A')));
+        assert!(modifiers().shift);
+
+        add_scancode(a_released);
+        add_scancode(shift_released);
+        assert_eq!(poll_key(), None);
+        assert!(!modifiers().shift);
+    }
+
+    #[test_case]
+    fn scancode_stream_yields_queued_scancodes() {
+        use futures_util::task::noop_waker_ref;
+
+        let mut stream = ScancodeStream::new();
+        let mut context = Context::from_waker(noop_waker_ref());
+
+        add_scancode(0x1e);
+        add_scancode(0x9e);
+
+        assert_eq!(stream.poll_next_unpin(&mut context), Poll::Ready(Some(0x1e)));
+        assert_eq!(stream.poll_next_unpin(&mut context), Poll::Ready(Some(0x9e)));
+        assert_eq!(stream.poll_next_unpin(&mut context), Poll::Pending);
+    }
+
+    #[test_case]
+    fn page_up_scrolls_the_screen_back() {
+        let page_up = [0xe0, 0x49];
+        let page_up_released = [0xe0, 0xc9];
+
+        let (_, height) = vga_buffer::dimensions();
+        for _ in 0..height {
+            println!();
+        }
+
+        let keys = page_up.iter()
+                          .chain(page_up_released.iter())
+                          .filter_map(|&scancode| decode(scancode));
+        for key in keys {
+            handle_key(key);
+        }
+        let view_offset = vga_buffer::view_offset();
+        vga_buffer::scroll_down(view_offset);
+
+        assert_eq!(view_offset, height - 1);
+    }
+
+    #[test_case]
+    fn ctrl_c_raises_the_interrupt_signal_instead_of_a_key() {
+        use core::sync::atomic::AtomicUsize;
+
+        static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+        fn count_interrupts(signal: Signal) {
+            if signal == Signal::Interrupt {
+                INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        register_signal_handler(count_interrupts);
+
+        // Ctrl, C, C released, Ctrl released
+        for &scancode in [0x1d, 0x2e, 0xae, 0x9d].iter() {
+            add_scancode(scancode);
+        }
+
+        assert_eq!(poll_key(), None);
+        assert_eq!(INTERRUPTS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test_case]
+    fn full_scancode_queue_hands_back_the_scancode() {
+        let queue: RingBuffer<u8, SCANCODE_QUEUE_CAPACITY> = RingBuffer::new();
+        for _ in 0..SCANCODE_QUEUE_CAPACITY {
+            assert_eq!(queue.try_push(1), Ok(()));
+        }
+
+        assert_eq!(queue.try_push(2), Err(2));
+        assert_eq!(queue.try_pop(), Some(1));
+    }
+}
@@ -7,6 +7,12 @@
 #![feature(alloc_error_handler)]
 #![feature(const_fn)]
 #![feature(const_in_array_repeat_expressions)]
+#![feature(asm)]
+#![feature(global_asm)]
+#![feature(wake_trait)]
+#![feature(const_generics)]
+#![feature(core_intrinsics)]
+#![allow(incomplete_features)]
 
 extern crate alloc;
 
@@ -16,23 +22,119 @@ pub mod interrupts;
 pub mod gdt;
 pub mod memory;
 pub mod allocator;
+pub mod time;
+pub mod keyboard;
+pub mod input;
+pub mod task;
+pub mod log;
+pub mod power;
+pub mod cpu;
+pub mod rand;
+pub mod rtc;
+pub mod sched;
+pub mod backtrace;
+pub mod hexdump;
+pub mod mmio;
+mod port_io;
+mod ps2;
+pub mod util;
+pub mod shell;
+pub mod panic_policy;
+pub mod kassert;
 
-use core::panic::PanicInfo;
-use bootloader::BootInfo;
+use core::{
+    fmt,
+    panic::PanicInfo,
+    sync::atomic::{
+        AtomicBool,
+        AtomicU64,
+        AtomicUsize,
+        Ordering,
+    },
+};
+use bootloader::{
+    BootInfo,
+    bootinfo::MemoryMap,
+};
+use interrupts::InterruptController;
+use memory::MemoryInitError;
 
 #[cfg(test)]
 use bootloader::entry_point;
 
-/// Performs system initialisation
-pub fn init(boot_info: &'static BootInfo) {
-    memory::init(boot_info);
+/// The errors which can occur during system initialisation
+#[derive(Debug)]
+pub enum InitError {
+    /// The memory, along with the Kernel Heap, could not be set up
+    Memory(MemoryInitError),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitError::Memory(error) => write!(f, "initialising the memory failed: {:?}", error),
+        }
+    }
+}
+
+/// Performs system initialisation, taking the hardware interrupts from `controller`
+///
+/// Nothing else is set up if a step fails, so the caller can only report the error.
+pub fn init(boot_info: &'static BootInfo, controller: InterruptController) -> Result<(), InitError> {
+    memory::init(boot_info).map_err(InitError::Memory)?;
     unsafe { 
-        allocator::init_heap(memory::HEAP_START, memory::HEAP_SIZE);
+        vga_buffer::init(memory::phys_mem_offset());
     }
+
     gdt::init();
     interrupts::init_idt();
     interrupts::init_pics();
+    interrupts::init_timer(interrupts::DEFAULT_TIMER_HZ);
+    if controller == InterruptController::Apic {
+        // The PICs stay in use if the CPU has no Local APIC
+        interrupts::init_apic();
+    }
     x86_64::instructions::interrupts::enable();
+
+    Ok(())
+}
+
+/// The amounts of memory described by a memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryMapSummary {
+    pub regions: usize,
+    pub usable_bytes: u64,
+    pub reserved_bytes: u64,
+}
+
+/// Sums the sizes of the usable and of all other regions in `memory_map`
+pub fn summarize_memory_map(memory_map: &MemoryMap) -> MemoryMapSummary {
+    let (total, usable) = memory::physical_memory_sizes(memory_map);
+
+    MemoryMapSummary {
+        regions: memory::all_regions(memory_map).count(),
+        usable_bytes: usable as u64,
+        reserved_bytes: (total - usable) as u64,
+    }
+}
+
+/// Prints the CPU vendor and a summary of the memory setup
+pub fn print_boot_info(boot_info: &'static BootInfo) {
+    const KIB: u64 = 1024;
+
+    let vendor = cpu::vendor();
+    let summary = summarize_memory_map(&boot_info.memory_map);
+    let heap_size = memory::stats().heap_size as u64;
+
+    println!("{:<18}{}", "CPU vendor", core::str::from_utf8(&vendor).unwrap_or("unknown"));
+    if summary.regions == 0 {
+        println!("{:<18}{}", "memory map", "empty");
+    } else {
+        println!("{:<18}{}", "memory regions", summary.regions);
+        println!("{:<18}{} KiB", "usable memory", summary.usable_bytes / KIB);
+        println!("{:<18}{} KiB", "reserved memory", summary.reserved_bytes / KIB);
+    }
+    println!("{:<18}{} KiB", "heap size", heap_size / KIB);
 }
 
 pub fn hlt_loop() -> ! {
@@ -41,31 +143,300 @@ pub fn hlt_loop() -> ! {
     }
 }
 
+/// Reports a kernel panic on the serial interface and on the screen
+///
+/// Interrupts are disabled first and the locks of both outputs are forcibly released,
+/// since the panicking code may hold them. Must only be called right before halting.
+pub fn report_panic(info: &PanicInfo) {
+    x86_64::instructions::interrupts::disable();
+    unsafe {
+        serial::force_unlock();
+        vga_buffer::force_unlock();
+    }
+
+    serial_println!("KERNEL PANIC");
+    serial_println!("{}", info);
+
+    vga_buffer::_print_colored(
+        vga_buffer::Color::Red,
+        format_args!("KERNEL PANIC\n")
+    );
+    println!("{}", info);
+
+    backtrace::backtrace();
+}
+
+/// The time a test may run for before it is reported as timed out, unless overridden
+pub const DEFAULT_TEST_TIMEOUT_MS: u64 = 5000;
+
+/// Marks that no test is running
+const NO_TEST: u64 = u64::MAX;
+
+/// The tick at which the running test started
+static TEST_STARTED_AT: AtomicU64 = AtomicU64::new(NO_TEST);
+
+static TEST_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_TEST_TIMEOUT_MS);
+
+/// Sets the time a test may run for, returning the previous one
+pub fn set_test_timeout_ms(ms: u64) -> u64 {
+    TEST_TIMEOUT_MS.swap(ms, Ordering::Relaxed)
+}
+
+/// Fails the test run if the running test exceeded its timeout
+///
+/// Called by the timer interrupt handler. Tests only time out while
+/// timer interrupts are enabled.
+pub(crate) fn check_test_timeout() {
+    let started_at = TEST_STARTED_AT.load(Ordering::Relaxed);
+    if started_at == NO_TEST {
+        return;
+    }
+
+    let elapsed_ms = time::ticks_to_ms(time::ticks() - started_at);
+    if elapsed_ms > TEST_TIMEOUT_MS.load(Ordering::Relaxed) {
+        // The test may have been interrupted while printing
+        unsafe {
+            serial::force_unlock();
+        }
+        serial_println!("[timed out]\n");
+        report_test_result(TestStatus::TimedOut);
+        exit_qemu(QemuExitCode::Failure);
+
+        hlt_loop();
+    }
+}
+
+/// Whether each test result is also reported as a `TestResult` line
+const STRUCTURED_TEST_RESULTS: bool = cfg!(feature = "structured-test-results");
+
+/// The name of the running test
+static RUNNING_TEST: spin::Mutex<&'static str> = spin::Mutex::new("");
+
+/// The numbers of tests which ran and passed so far
+static TESTS_RUN: AtomicUsize = AtomicUsize::new(0);
+static TESTS_PASSED: AtomicUsize = AtomicUsize::new(0);
+
+/// The outcome of a test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Ok,
+    Failed,
+    TimedOut,
+}
+
+impl TestStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            TestStatus::Ok       => "ok",
+            TestStatus::Failed   => "failed",
+            TestStatus::TimedOut => "timed_out",
+        }
+    }
+}
+
+/// The machine-readable line reporting the outcome of a test,
+/// like `RESULT name=myos::tests::works status=ok duration_ms=10`
+pub struct TestResult<'a> {
+    pub name: &'a str,
+    pub status: TestStatus,
+    pub duration_ms: u64,
+}
+
+impl fmt::Display for TestResult<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "RESULT name={} status={} duration_ms={}",
+            self.name,
+            self.status.as_str(),
+            self.duration_ms,
+        )
+    }
+}
+
+/// The machine-readable line summing up the test run,
+/// like `SUMMARY total=3 passed=2 failed=1`
+pub struct TestSummary {
+    pub total: usize,
+    pub passed: usize,
+}
+
+impl fmt::Display for TestSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SUMMARY total={} passed={} failed={}",
+            self.total,
+            self.passed,
+            self.total - self.passed,
+        )
+    }
+}
+
+/// The line summing up the test run like libtest does,
+/// like `test result: ok. 3 passed; 0 failed`
+pub struct TestRunSummary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for TestRunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "test result: {}. {} passed; {} failed",
+            if self.failed == 0 { "ok" } else { "FAILED" },
+            self.passed,
+            self.failed,
+        )
+    }
+}
+
+/// Counts the outcome of the running test and reports it on serial:
+/// a passed test with its duration, and any test as a `RESULT` line
+/// when structured test results are enabled.
+///
+/// A test which did not pass ends the test run, so the summary is reported along with it.
+fn report_test_result(status: TestStatus) {
+    let started_at = TEST_STARTED_AT.load(Ordering::Relaxed);
+    let duration_ms = if started_at == NO_TEST { 0 } else { time::ticks_to_ms(time::ticks() - started_at) };
+
+    let run = TESTS_RUN.fetch_add(1, Ordering::Relaxed) + 1;
+    let passed = if status == TestStatus::Ok {
+        TESTS_PASSED.fetch_add(1, Ordering::Relaxed) + 1
+    } else {
+        TESTS_PASSED.load(Ordering::Relaxed)
+    };
+
+    if status == TestStatus::Ok {
+        serial_println!("[ok] ({} ms)", duration_ms);
+    }
+    if STRUCTURED_TEST_RESULTS {
+        // The runner may hold the lock if its test timed out
+        let name = RUNNING_TEST.try_lock().map_or("unknown", |name| *name);
+
+        serial_println!("{}", TestResult { name, status, duration_ms });
+    }
+    if status != TestStatus::Ok {
+        print_test_summary(run, passed);
+    }
+}
+
+/// Counts the running test as passed and reports it with its duration
+fn pass_test() {
+    report_test_result(TestStatus::Ok);
+}
+
+fn report_test_summary() {
+    print_test_summary(TESTS_RUN.load(Ordering::Relaxed), TESTS_PASSED.load(Ordering::Relaxed));
+}
+
+fn print_test_summary(total: usize, passed: usize) {
+    serial_println!("\n{}", TestRunSummary { passed, failed: total - passed });
+    if STRUCTURED_TEST_RESULTS {
+        serial_println!("{}", TestSummary { total, passed });
+    }
+}
+
 pub fn test_runner(tests: &[&dyn Testable]) {
     serial_println!("Running {} tests", tests.len());
 
     for test in tests {
+        *RUNNING_TEST.lock() = test.name();
+        TEST_STARTED_AT.store(time::ticks(), Ordering::Relaxed);
         test.run();
+        pass_test();
+        TEST_STARTED_AT.store(NO_TEST, Ordering::Relaxed);
     }
 
+    report_test_summary();
     exit_qemu(QemuExitCode::Success);
 }
 
 pub trait Testable {
     fn run(&self);
+    fn name(&self) -> &'static str;
 }
 
 impl<T> Testable for T where T: Fn() {
     fn run(&self) {
-        serial_print!("{}...\t", core::any::type_name::<T>());
+        serial_print!("{} ... ", self.name());
         self();
-        serial_println!("[ok]");
+    }
+
+    fn name(&self) -> &'static str {
+        core::any::type_name::<T>()
     }
 }
 
+/// Set while a `ShouldPanic` test runs
+static EXPECTING_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// A test which passes only if it panics
+///
+/// There is no unwinding, so the panic ends the test run: a `ShouldPanic`
+/// test must be the last test of its test binary, best the only one.
+/// It is registered as a `#[test_case]` constant made with `should_panic!`:
+///
+/// ```ignore
+/// #[test_case]
+/// const FAILED_ASSERTION_PANICS: ShouldPanic = should_panic!(failed_assertion_panics);
+///
+/// fn failed_assertion_panics() {
+///     assert_eq!(1, 2);
+/// }
+/// ```
+pub struct ShouldPanic {
+    pub name: &'static str,
+    pub test: fn(),
+}
+
+/// Makes a `ShouldPanic` test of the given function, named by its path
+#[macro_export]
+macro_rules! should_panic {
+    ($test:ident) => {
+        $crate::ShouldPanic {
+            name: concat!(module_path!(), "::", stringify!($test)),
+            test: $test,
+        }
+    };
+}
+
+impl Testable for ShouldPanic {
+    fn run(&self) {
+        serial_print!("{} ... ", self.name());
+        EXPECTING_PANIC.store(true, Ordering::SeqCst);
+        (self.test)();
+        EXPECTING_PANIC.store(false, Ordering::SeqCst);
+
+        serial_println!("[failed]\n");
+        serial_println!("Error: the test did not panic\n");
+        report_test_result(TestStatus::Failed);
+        exit_qemu(QemuExitCode::Failure);
+
+        hlt_loop();
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Reports the failure of the running test and exits QEMU,
+/// or reports success if the test is a `ShouldPanic` one.
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    if EXPECTING_PANIC.load(Ordering::SeqCst) {
+        pass_test();
+        report_test_summary();
+        exit_qemu(QemuExitCode::Success);
+
+        hlt_loop();
+    }
+
     serial_println!("[failed]\n");
     serial_println!("Error: {}\n", info);
+    backtrace::backtrace();
+    report_test_result(TestStatus::Failed);
 
     exit_qemu(QemuExitCode::Failure);
 
@@ -93,7 +464,7 @@ entry_point!(test_kernel_main);
 
 #[cfg(test)]
 fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
-    init(boot_info);
+    init(boot_info, InterruptController::Pic).expect("Initialization failed");
 
     test_main();
 
@@ -109,4 +480,71 @@ fn panic(info: &PanicInfo) -> ! {
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     panic!("allocation error: {:?}", layout)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bootloader::bootinfo::MemoryRegionType;
+    use memory::memory_map_from;
+
+    #[test_case]
+    fn memory_map_summary_separates_usable_regions() {
+        let memory_map = memory_map_from(&[
+            (0x0000, 0x1000, MemoryRegionType::FrameZero),
+            (0x1000, 0x9000, MemoryRegionType::Usable),
+            (0x9000, 0xa000, MemoryRegionType::Reserved),
+            (0x10_0000, 0x20_0000, MemoryRegionType::Usable),
+        ]);
+
+        assert_eq!(
+            summarize_memory_map(&memory_map),
+            MemoryMapSummary {
+                regions: 4,
+                usable_bytes: 0x8000 + 0x10_0000,
+                reserved_bytes: 0x2000,
+            }
+        );
+    }
+
+    #[test_case]
+    fn empty_memory_map_has_an_empty_summary() {
+        assert_eq!(summarize_memory_map(&memory_map_from(&[])), MemoryMapSummary::default());
+    }
+
+    #[test_case]
+    fn test_result_line_has_the_name_status_and_duration() {
+        use alloc::format;
+
+        let result = TestResult {
+            name: "myos::tests::works",
+            status: TestStatus::TimedOut,
+            duration_ms: 5010,
+        };
+        let summary = TestSummary {
+            total: 3,
+            passed: 2,
+        };
+
+        assert_eq!(format!("{}", result), "RESULT name=myos::tests::works status=timed_out duration_ms=5010");
+        assert_eq!(format!("{}", summary), "SUMMARY total=3 passed=2 failed=1");
+    }
+
+    #[test_case]
+    fn test_run_summary_line_has_the_counts() {
+        use alloc::format;
+
+        let passed = TestRunSummary { passed: 12, failed: 0 };
+        let failed = TestRunSummary { passed: 2, failed: 1 };
+
+        assert_eq!(format!("{}", passed), "test result: ok. 12 passed; 0 failed");
+        assert_eq!(format!("{}", failed), "test result: FAILED. 2 passed; 1 failed");
+    }
+
+    #[test_case]
+    fn test_running_below_its_timeout_does_not_time_out() {
+        let previous = set_test_timeout_ms(500);
+        time::sleep_ms(300);
+        set_test_timeout_ms(previous);
+    }
+}
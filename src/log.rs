@@ -0,0 +1,180 @@
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicU8,
+        Ordering,
+    },
+};
+use crate::{
+    serial,
+    time,
+    vga_buffer::{
+        self,
+        Color,
+    },
+};
+
+/// The severity of a log message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn from_u8(level: u8) -> Level {
+        match level {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn  => "WARN",
+            Level::Info  => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    /// The color of the messages of the level, which for `Error`, `Warn` and `Info` is the one of the theme
+    fn color(self) -> Color {
+        let theme = vga_buffer::theme();
+
+        match self {
+            Level::Error => theme.error_fg,
+            Level::Warn  => theme.warn_fg,
+            Level::Info  => theme.info_fg,
+            Level::Debug => Color::LightGray,
+            Level::Trace => Color::DarkGray,
+        }
+    }
+}
+
+/// The most verbose level of the messages which are logged
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Sets the most verbose level of the messages which are logged
+pub fn set_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns the most verbose level of the messages which are logged
+pub fn level() -> Level {
+    Level::from_u8(MAX_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Checks if messages with the given level are logged
+pub fn is_enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Logs a message to the VGA text buffer and the serial interface
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    if !is_enabled(level) {
+        return;
+    }
+
+    let line = Line {
+        level,
+        uptime_ms: time::uptime_ms(),
+        args,
+    };
+
+    vga_buffer::_print_colored(level.color(), format_args!("{}", line));
+    serial::_print(format_args!("{}", line));
+}
+
+/// A log message prefixed with its uptime timestamp and level
+struct Line<'a> {
+    level: Level,
+    uptime_ms: u64,
+    args: fmt::Arguments<'a>,
+}
+
+impl fmt::Display for Line<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "[{:>5}.{:03}] {:<5} {}",
+            self.uptime_ms / 1000,
+            self.uptime_ms % 1000,
+            self.level.tag(),
+            self.args
+        )
+    }
+}
+
+/// Logs a message with the `Error` level
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Error, format_args!($($arg)*)));
+}
+
+/// Logs a message with the `Warn` level
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Warn, format_args!($($arg)*)));
+}
+
+/// Logs a message with the `Info` level
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Info, format_args!($($arg)*)));
+}
+
+/// Logs a message with the `Debug` level
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Debug, format_args!($($arg)*)));
+}
+
+/// Logs a message with the `Trace` level
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Trace, format_args!($($arg)*)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vga_buffer::{
+        screen_contains,
+        screen_text_color,
+        Theme,
+    };
+
+    #[test_case]
+    fn only_messages_up_to_the_max_level_are_logged() {
+        set_level(Level::Warn);
+        crate::info!("suppressed info message");
+        crate::error!("logged error message");
+        set_level(Level::Info);
+
+        assert!(!screen_contains("suppressed info message"));
+        assert!(screen_contains("logged error message"));
+    }
+
+    #[test_case]
+    fn writer_and_log_messages_use_the_colors_of_the_theme() {
+        let theme = Theme::high_contrast();
+
+        vga_buffer::set_theme(theme);
+        let writer_color = vga_buffer::WRITER.lock().color();
+        crate::warn!("themed warning");
+        vga_buffer::set_theme(Theme::dark());
+
+        assert_eq!(writer_color, (theme.default_fg, theme.default_bg));
+        assert_eq!(screen_text_color("themed warning"), Some(theme.warn_fg));
+    }
+}
@@ -6,7 +6,12 @@
 
 extern crate alloc;
 
-use myos::println;
+use myos::{
+    interrupts::InterruptController,
+    println,
+    panic_policy,
+    shell,
+};
 use core::panic::PanicInfo;
 use bootloader::{
     BootInfo,
@@ -16,21 +21,23 @@ use bootloader::{
 entry_point!(kernel_main);
 
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
-    myos::init(boot_info);
+    myos::init(boot_info, InterruptController::Apic).expect("Initialization failed");
 
     println!("Welcome to myos!");
+    myos::print_boot_info(boot_info);
 
     #[cfg(test)]
     test_main();
 
-    myos::hlt_loop();
+    panic_policy::reset_after_stable_uptime();
+    shell::run();
 }
 
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    myos::hlt_loop();
+    myos::report_panic(info);
+    panic_policy::reboot_or_halt();
 }
 
 #[cfg(test)]
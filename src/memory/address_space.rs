@@ -0,0 +1,129 @@
+use x86_64::{
+    registers::control::{
+        Cr3,
+        Cr3Flags,
+    },
+    structures::paging::{
+        mapper::MapToError,
+        FrameAllocator,
+        OffsetPageTable,
+        PageTable,
+        PhysFrame,
+        Size4KiB,
+    },
+};
+use super::{
+    frame_ptr,
+    phys_mem_offset,
+};
+
+/// A set of page tables with its own level 4 table
+///
+/// The frames of its page tables are not deallocated when it is dropped.
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+}
+
+impl AddressSpace {
+    /// Returns the frame of the level 4 table, which is loaded in `Cr3` on switching to it
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    /// Returns a mapper for the page tables of the address space
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        unsafe {
+            OffsetPageTable::new(&mut *level_4_table(self.level_4_frame), phys_mem_offset())
+        }
+    }
+
+    /// Makes the address space the active one
+    ///
+    /// ## Safety
+    ///
+    /// The caller must guarantee that the code, the stack and all the other memory in use
+    /// are mapped the same way in the address space.
+    pub unsafe fn switch_to(&self) {
+        Cr3::write(self.level_4_frame, Cr3Flags::empty());
+    }
+}
+
+/// Creates an address space with the Kernel mappings of the active one
+///
+/// The Kernel is not linked to the higher half, so all used entries of the active
+/// level 4 table are copied. The lower level tables they point to are shared,
+/// so mappings in the regions they cover show up in both address spaces,
+/// while mappings in the regions of the unused entries are private.
+pub fn new_address_space<F>(frame_allocator: &mut F) -> Result<AddressSpace, MapToError<Size4KiB>>
+where
+    F: FrameAllocator<Size4KiB>,
+{
+    let level_4_frame = frame_allocator
+        .allocate_frame()
+        .ok_or(MapToError::FrameAllocationFailed)?;
+
+    let (active_level_4_frame, _) = Cr3::read();
+    unsafe {
+        let active_table = &*level_4_table(active_level_4_frame);
+        let table = level_4_table(level_4_frame);
+
+        table.write(PageTable::new());
+        for (entry, active_entry) in (*table).iter_mut().zip(active_table.iter()) {
+            if !active_entry.is_unused() {
+                *entry = active_entry.clone();
+            }
+        }
+    }
+
+    Ok(AddressSpace {
+        level_4_frame,
+    })
+}
+
+/// The level 4 table in the given frame, through the mapping of the complete physical memory
+fn level_4_table(frame: PhysFrame) -> *mut PageTable {
+    frame_ptr(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{
+        self,
+        translate_addr,
+    };
+    use x86_64::structures::paging::{
+        Mapper,
+        Page,
+        PageTableFlags,
+        PageTableIndex,
+    };
+
+    #[test_case]
+    fn mappings_in_a_new_address_space_do_not_affect_the_active_one() {
+        let (active_level_4_frame, _) = Cr3::read();
+        let active_table = unsafe { &*level_4_table(active_level_4_frame) };
+        let unused_index = (1..256usize).find(|&i| active_table[i].is_unused())
+                                       .expect("No unused level 4 entry");
+        let index = |i: u16| PageTableIndex::new(i);
+        let page: Page = Page::from_page_table_indices(index(unused_index as u16), index(0), index(0), index(0));
+
+        memory::with_kernel_memory(|_, frame_allocator| {
+            let mut address_space = new_address_space(frame_allocator).expect("Creating the address space failed");
+            let frame: PhysFrame = frame_allocator.allocate_frame().expect("No frames left");
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+            unsafe {
+                address_space.mapper()
+                             .map_to(page, frame, flags, frame_allocator)
+                             .expect("Mapping failed")
+                             .ignore();
+            }
+
+            assert_eq!(address_space.mapper().translate_page(page).ok(), Some(frame));
+        });
+
+        assert!(active_table[unused_index].is_unused());
+        assert!(translate_addr(page.start_address()).is_none());
+    }
+}
@@ -4,24 +4,30 @@ use x86_64::{
         paging::{
             PhysFrame,
             FrameAllocator,
+            FrameDeallocator,
+            PageSize,
+            Size2MiB,
             Size4KiB,
         },
     },
 };
-use bootloader::{
-    bootinfo::{
-        MemoryMap,
-        MemoryRegionType,
-    },
-};
+use bootloader::bootinfo::MemoryMap;
 use super::{
+    self as memory,
     PAGE_SIZE,
 };
 
+/// Ends the list of deallocated frames. Frame zero is never usable.
+const END_OF_FREE_LIST: u64 = 0;
+
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
+///
+/// Deallocated frames are handed out again before the unused ones of the memory map.
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    /// The last deallocated frame, which holds the address of the one deallocated before it
+    free_list: Option<PhysFrame>,
 }
 
 impl BootInfoFrameAllocator {
@@ -34,17 +40,29 @@ impl BootInfoFrameAllocator {
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            free_list: None,
+        }
+    }
+
+    /// Allocates a frame and fills it with zeros.
+    ///
+    /// The frame is written through the mapping of the complete physical memory,
+    /// so this panics if the memory is not initialised yet.
+    pub fn allocate_zeroed_frame(&mut self) -> Option<PhysFrame> {
+        let frame: PhysFrame = self.allocate_frame()?;
+
+        unsafe {
+            memory::frame_ptr::<u8>(frame).write_bytes(0, PAGE_SIZE);
         }
+
+        Some(frame)
     }
 
     /// Returns an iterator over the usable frames specified in the memory map.
     fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        self.memory_map.iter()
-            .filter(|region| {
-                region.region_type == MemoryRegionType::Usable
-            })
+        memory::usable_regions(self.memory_map)
             .map(|region| {
-                region.range.start_addr()..region.range.end_addr()
+                region.start.as_u64()..region.end.as_u64()
             })
             .flat_map(|address_range| {
                 //all usable regions are page-aligned by the bootloader
@@ -60,9 +78,128 @@ impl BootInfoFrameAllocator {
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.free_list.take() {
+            let next_free = unsafe { *free_list_link(frame) };
+            if next_free != END_OF_FREE_LIST {
+                self.free_list = Some(PhysFrame::containing_address(PhysAddr::new(next_free)));
+            }
+
+            return Some(frame);
+        }
+
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
 
         frame
     }
-}
\ No newline at end of file
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    /// Adds the frame to the list of deallocated frames, storing the link in its first bytes.
+    ///
+    /// ## Safety
+    ///
+    /// The frame must have been allocated by this allocator and must be unused.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let next_free = self.free_list.map_or(END_OF_FREE_LIST, |free| free.start_address().as_u64());
+
+        *free_list_link(frame) = next_free;
+        self.free_list = Some(frame);
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BootInfoFrameAllocator {
+    /// Deallocates the 4 KiB frames of the huge frame, which are then only allocated one by one.
+    ///
+    /// ## Safety
+    ///
+    /// The frame must have been allocated by this allocator and must be unused.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let start = frame.start_address();
+
+        for offset in (0..Size2MiB::SIZE).step_by(PAGE_SIZE) {
+            FrameDeallocator::<Size4KiB>::deallocate_frame(
+                self,
+                PhysFrame::containing_address(start + offset)
+            );
+        }
+    }
+}
+
+/// The location in the deallocated frame of the address of the next deallocated one
+fn free_list_link(frame: PhysFrame) -> *mut u64 {
+    memory::frame_ptr(frame)
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BootInfoFrameAllocator {
+    /// Allocates the first run of 512 contiguous usable frames starting at a 2 MiB boundary.
+    ///
+    /// The usable frames skipped while looking for such a run are not allocated afterwards.
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let frames_per_huge_frame = (Size2MiB::SIZE / Size4KiB::SIZE) as usize;
+        let mut run: Option<(usize, PhysAddr)> = None;
+
+        for (i, frame) in self.usable_frames().enumerate().skip(self.next) {
+            let address = frame.start_address();
+
+            run = match run {
+                Some((start, start_address))
+                    if address == start_address + (i - start) * PAGE_SIZE => run,
+                _ if address.is_aligned(Size2MiB::SIZE) => Some((i, address)),
+                _ => None,
+            };
+
+            if let Some((start, start_address)) = run {
+                if i - start + 1 == frames_per_huge_frame {
+                    self.next = i + 1;
+                    return Some(PhysFrame::containing_address(start_address));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[test_case]
+    fn zeroed_frame_has_only_zero_bytes() {
+        memory::with_kernel_memory(|_, frame_allocator| {
+            let next_frame = frame_allocator.free_list
+                                            .or_else(|| frame_allocator.usable_frames().nth(frame_allocator.next))
+                                            .expect("No frames left");
+            let contents = memory::frame_ptr::<u8>(next_frame);
+            unsafe {
+                contents.write_bytes(0xab, PAGE_SIZE);
+            }
+
+            let frame = frame_allocator.allocate_zeroed_frame();
+            assert_eq!(frame, Some(next_frame));
+
+            let contents = unsafe {
+                core::slice::from_raw_parts(contents, PAGE_SIZE)
+            };
+            assert!(contents.iter().all(|&b| b == 0));
+        });
+    }
+
+    #[test_case]
+    fn deallocated_frames_are_allocated_again_last_first() {
+        memory::with_kernel_memory(|_, frame_allocator| {
+            let first: PhysFrame = frame_allocator.allocate_frame().expect("No frames left");
+            let second: PhysFrame = frame_allocator.allocate_frame().expect("No frames left");
+
+            unsafe {
+                frame_allocator.deallocate_frame(first);
+                frame_allocator.deallocate_frame(second);
+            }
+
+            assert_eq!(frame_allocator.allocate_frame(), Some(second));
+            assert_eq!(frame_allocator.allocate_frame(), Some(first));
+        });
+    }
+}
@@ -1,18 +1,58 @@
+mod address_space;
 mod boot_info_frame_allocator;
+mod regions;
+mod stats;
 
-use boot_info_frame_allocator::BootInfoFrameAllocator;
-use bootloader::BootInfo;
+pub use address_space::{
+    new_address_space,
+    AddressSpace,
+};
+pub use boot_info_frame_allocator::BootInfoFrameAllocator;
+pub use regions::{
+    all_regions,
+    usable_regions,
+    PhysRegion,
+};
+pub use stats::{
+    stats,
+    MemoryStats,
+};
+pub(crate) use stats::physical_memory_sizes;
+use bootloader::{
+    BootInfo,
+    bootinfo::MemoryMap,
+};
+use crate::{
+    allocator,
+    util::Once,
+};
+use core::sync::atomic::{
+    AtomicU64,
+    AtomicUsize,
+    Ordering,
+};
 use x86_64::{
+    PhysAddr,
     VirtAddr,
     structures::{
         paging::{
             PageTable,
             OffsetPageTable,
-            mapper::MapToError,
+            mapper::{
+                FlagUpdateError,
+                MapToError,
+                TranslateError,
+                UnmapError,
+            },
             FrameAllocator,
+            FrameDeallocator,
             Mapper,
+            MapperAllSizes,
             Page,
+            PhysFrame,
+            PageSize,
             PageTableFlags,
+            Size2MiB,
             Size4KiB,
             page::PageRangeInclusive,
         },
@@ -20,19 +60,103 @@ use x86_64::{
 };
 
 /// The Page size in bytes
-const PAGE_SIZE: usize = 4096;
+pub const PAGE_SIZE: usize = 4096;
 
 /// The start of the region of Virtual Memory allocated for the Heap
+///
+/// The page right below it and the page right after the end of the Heap are left unmapped
+/// as guards, so that under- and overruns of the Heap fault instead of corrupting other mappings.
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 
-/// The size of the Heap in bytes
-pub const HEAP_SIZE: usize = 100 * 1024;
+/// The largest size of the Heap set up by `init`, in bytes
+///
+/// The Heap can still grow past it at runtime.
+pub const MAX_HEAP_SIZE: usize = 1024 * 1024;
+
+/// The smallest size of the Heap set up by `init`, in bytes
+///
+/// If there is not enough usable physical memory for it, mapping the Heap fails.
+pub const MIN_HEAP_SIZE: usize = 64 * 1024;
+
+/// The initial Heap takes at most this fraction of the usable physical memory
+const HEAP_SHARE_OF_USABLE_MEMORY: usize = 4;
+
+/// The virtual address at which the complete physical memory is mapped
+static PHYS_MEM_OFFSET: Once<VirtAddr> = Once::new();
+
+/// The end of the mapped region of the Heap
+static HEAP_END: AtomicUsize = AtomicUsize::new(HEAP_START);
+
+/// The errors which can occur while initialising the memory
+#[derive(Debug)]
+pub enum MemoryInitError {
+    /// The memory is initialised already
+    AlreadyInitialised,
+    HeapMappingFailed(MapToError<Size4KiB>),
+    /// A page right around the Heap is mapped, so it cannot guard the Heap
+    GuardPageMapped(Page<Size4KiB>),
+}
+
+/// The errors which can occur while growing the Heap
+#[derive(Debug)]
+pub enum HeapGrowthError {
+    /// The Kernel memory is not initialised or it is in use by the caller
+    MemoryUnavailable,
+    MappingFailed(MapToError<Size4KiB>),
+    /// The page after the grown Heap is mapped, so it cannot guard the Heap
+    GuardPageMapped,
+}
+
+/// The errors which can occur while shrinking the Heap
+#[derive(Debug)]
+pub enum HeapShrinkError {
+    /// The Kernel memory is not initialised or it is in use by the caller
+    MemoryUnavailable,
+    /// Some of the pages are used by the allocator or they are all of the Heap
+    HeapInUse,
+    /// Some of the pages cannot be unmapped, so the Heap is left as it was
+    UnmappingFailed(UnmapError),
+}
+
+/// The start of the region of Virtual Memory physical regions are mapped to
+const PHYSICAL_REGIONS_START: u64 = 0x_6666_6666_0000;
+
+/// The next free address of the region physical regions are mapped to
+static NEXT_PHYSICAL_REGION: AtomicU64 = AtomicU64::new(PHYSICAL_REGIONS_START);
+
+/// The start of the region of Virtual Memory stacks are allocated in
+const STACKS_START: u64 = 0x_3333_3333_0000;
+
+/// The start of the next stack's guard page
+static NEXT_STACK: AtomicU64 = AtomicU64::new(STACKS_START);
+
+/// The start of the region of Virtual Memory large allocations are mapped in
+const LARGE_ALLOCATIONS_START: u64 = 0x_2222_2222_0000;
+
+/// The start of the next large allocation's guard page
+///
+/// Virtual addresses of freed large allocations are not reused, the region is large enough.
+static NEXT_LARGE_ALLOCATION: AtomicU64 = AtomicU64::new(LARGE_ALLOCATIONS_START);
+
+/// The page table mapper and the frame allocator set up by `init`
+struct KernelMemory {
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+}
+
+static KERNEL_MEMORY: spin::Mutex<Option<KernelMemory>> = spin::Mutex::new(None);
 
 /// Further sets up the Kernel virtual memory.
 ///
-/// Maps the region allocated for the Heap to physical memory.
-pub fn init(boot_info: &'static BootInfo) {
+/// Maps a region of `recommended_heap_size` bytes for the Heap to physical memory
+/// and initializes the global allocator with it. Fails if the Heap cannot be mapped
+/// or if one of its guard pages is mapped.
+/// Must be called before anything is allocated. Fails without any effect if called again.
+pub fn init(boot_info: &'static BootInfo) -> Result<(), MemoryInitError> {
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    PHYS_MEM_OFFSET
+        .set(phys_mem_offset)
+        .map_err(|_| MemoryInitError::AlreadyInitialised)?;
     let mut mapper = unsafe { 
         init_page_table_mapper(phys_mem_offset)
     };
@@ -40,8 +164,108 @@ pub fn init(boot_info: &'static BootInfo) {
         BootInfoFrameAllocator::init(&boot_info.memory_map)
     };
 
-    map_heap_to_physical_memory(&mut mapper, &mut frame_allocator)
-        .expect("Heap initialization failed");
+    let heap_size = recommended_heap_size(boot_info);
+    map_heap(&mut mapper, &mut frame_allocator, HEAP_START, heap_size)?;
+    HEAP_END.store(HEAP_START + heap_size, Ordering::Relaxed);
+    unsafe {
+        allocator::init_heap(HEAP_START, heap_size);
+    }
+
+    stats::record_physical_memory(&boot_info.memory_map);
+
+    *KERNEL_MEMORY.lock() = Some(KernelMemory {
+        mapper,
+        frame_allocator,
+    });
+
+    Ok(())
+}
+
+/// Maps a Heap of `heap_size` bytes at `heap_start`, checking that its guard pages are left unmapped
+fn map_heap<M, F>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    heap_start: usize,
+    heap_size: usize,
+) -> Result<(), MemoryInitError>
+where 
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+    F: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB>
+     + FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB>,
+{
+    map_heap_to_physical_memory(mapper, frame_allocator, heap_start, heap_size)
+        .map_err(MemoryInitError::HeapMappingFailed)?;
+    for &guard_page in heap_guard_pages(heap_start, heap_start + heap_size).iter() {
+        if Mapper::<Size4KiB>::translate_page(mapper, guard_page).is_ok() {
+            return Err(MemoryInitError::GuardPageMapped(guard_page));
+        }
+    }
+
+    Ok(())
+}
+
+/// The pages right below the start and right after the end of a Heap
+fn heap_guard_pages(heap_start: usize, heap_end: usize) -> [Page<Size4KiB>; 2] {
+    [
+        Page::containing_address(VirtAddr::new((heap_start - PAGE_SIZE) as u64)),
+        Page::containing_address(VirtAddr::new(heap_end as u64)),
+    ]
+}
+
+/// Returns the size of the Heap for the usable physical memory described by the boot info
+///
+/// It is a fraction of the usable memory, from `MIN_HEAP_SIZE` to `MAX_HEAP_SIZE`, in whole pages.
+pub fn recommended_heap_size(boot_info: &BootInfo) -> usize {
+    heap_size_for(&boot_info.memory_map)
+}
+
+fn heap_size_for(memory_map: &MemoryMap) -> usize {
+    let (_, usable) = stats::physical_memory_sizes(memory_map);
+    let size = (usable / HEAP_SHARE_OF_USABLE_MEMORY).min(MAX_HEAP_SIZE);
+
+    (size - size % PAGE_SIZE).max(MIN_HEAP_SIZE)
+}
+
+/// Returns the virtual address at which the complete physical memory is mapped
+///
+/// Panics if the memory is not initialised yet.
+pub fn phys_mem_offset() -> VirtAddr {
+    *PHYS_MEM_OFFSET.expect("Physical memory offset used before initialisation")
+}
+
+/// Calls `f` with the bytes of the frame, through the mapping of the complete physical memory
+///
+/// Panics if the memory is not initialised yet.
+///
+/// ## Safety
+///
+/// This function is unsafe because the caller must guarantee that the frame is not
+/// accessed in any other way for the duration of `f`: it must not back any live Rust object
+/// or a page table in use, and `f` must not call this function for the same frame.
+pub unsafe fn with_mapped_frame<R>(frame: PhysFrame, f: impl FnOnce(&mut [u8; PAGE_SIZE]) -> R) -> R {
+    f(&mut *frame_ptr(frame))
+}
+
+/// The start of the frame in the mapping of the complete physical memory
+///
+/// Panics if the memory is not initialised yet.
+fn frame_ptr<T>(frame: PhysFrame) -> *mut T {
+    (phys_mem_offset() + frame.start_address().as_u64()).as_mut_ptr()
+}
+
+/// Calls `f` with the Kernel's page table mapper and frame allocator
+///
+/// Panics if the memory is not initialised yet.
+pub fn with_kernel_memory<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut OffsetPageTable<'static>, &mut BootInfoFrameAllocator) -> R,
+{
+    let mut memory = KERNEL_MEMORY.lock();
+    let memory = memory
+        .as_mut()
+        .expect("Kernel memory used before initialisation");
+
+    f(&mut memory.mapper, &mut memory.frame_allocator)
 }
 
 /// Initialize an OffsetPageTable with the Kernel's level 4 page table
@@ -68,25 +292,24 @@ unsafe fn init_page_table_mapper(physical_memory_offset: VirtAddr) -> OffsetPage
 fn map_heap_to_physical_memory<M, F>(
     mapper: &mut M,
     frame_allocator: &mut F,
+    heap_start: usize,
+    heap_size: usize,
 ) -> Result<(), MapToError<Size4KiB>>
 where 
-    M: Mapper<Size4KiB>,
-    F: FrameAllocator<Size4KiB>,
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+    F: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB>
+     + FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB>,
 {
     let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-    let heap_pages = region_pages(
-        VirtAddr::new(HEAP_START as u64),
-        VirtAddr::new(
-            (HEAP_START + HEAP_SIZE - 1) as u64
-        ),
-    );
 
-    map_pages_to_physical_memory(
+    map_region_huge(
         mapper,
         frame_allocator,
-        heap_pages,
+        VirtAddr::new(heap_start as u64),
+        heap_size,
         flags,
     )
+    .map(|_| ())
 }
 
 /// Converts a virtual memory region to a range of its constituent pages
@@ -103,26 +326,945 @@ fn region_pages(start_address: VirtAddr, end_address: VirtAddr) -> PageRangeIncl
 ///
 /// For each page, the function allocates a new physical frame with the `frame_allocator`
 /// and then uses the `map_to` function of the `mapper` to map the page to that frame with `flags` and `frame_allocator`.
-fn map_pages_to_physical_memory<M, F>(
+/// If a page cannot be mapped, the pages mapped so far are unmapped and their frames deallocated,
+/// so either all pages are mapped or none is. The page tables created meanwhile are kept.
+fn map_pages_to_physical_memory<M, F, S>(
     mapper: &mut M,
     frame_allocator: &mut F,
-    pages: PageRangeInclusive<Size4KiB>,
+    pages: PageRangeInclusive<S>,
     flags: PageTableFlags,
-) -> Result<(), MapToError<Size4KiB>>
+) -> Result<(), MapToError<S>>
+where 
+    S: PageSize,
+    M: Mapper<S>,
+    F: FrameAllocator<S> + FrameAllocator<Size4KiB> + FrameDeallocator<S>,
+{
+    for (mapped, page) in pages.enumerate() {
+        let result = FrameAllocator::<S>::allocate_frame(frame_allocator)
+            .ok_or(MapToError::FrameAllocationFailed)
+            .and_then(|frame| {
+                map_page(mapper, frame_allocator, page, frame, flags).map_err(|error| {
+                    unsafe {
+                        frame_allocator.deallocate_frame(frame);
+                    }
+                    error
+                })
+            });
+
+        if let Err(error) = result {
+            unmap_pages(mapper, frame_allocator, pages.take(mapped));
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Unmaps the given mapped pages and deallocates their frames
+fn unmap_pages<M, F, S>(mapper: &mut M, frame_allocator: &mut F, pages: impl Iterator<Item = Page<S>>)
+where
+    S: PageSize,
+    M: Mapper<S>,
+    F: FrameDeallocator<S>,
+{
+    for page in pages {
+        if let Ok((frame, fl)) = mapper.unmap(page) {
+            fl.flush();
+            unsafe {
+                frame_allocator.deallocate_frame(frame);
+            }
+        }
+    }
+}
+
+/// Unmaps the given mapped pages and deallocates their frames
+///
+/// The pages are checked first, so either all of them are unmapped or, if one of them
+/// cannot be, none is and the error of the first such page is returned.
+fn try_unmap_pages<M, F, S>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    pages: PageRangeInclusive<S>,
+) -> Result<(), UnmapError>
+where
+    S: PageSize,
+    M: Mapper<S>,
+    F: FrameDeallocator<S>,
+{
+    for page in pages {
+        mapper.translate_page(page).map_err(into_unmap_error)?;
+    }
+
+    for page in pages {
+        let (frame, fl) = mapper.unmap(page)?;
+        fl.flush();
+        unsafe {
+            frame_allocator.deallocate_frame(frame);
+        }
+    }
+
+    Ok(())
+}
+
+fn into_unmap_error(error: TranslateError) -> UnmapError {
+    match error {
+        TranslateError::PageNotMapped             => UnmapError::PageNotMapped,
+        TranslateError::ParentEntryHugePage       => UnmapError::ParentEntryHugePage,
+        TranslateError::InvalidFrameAddress(addr) => UnmapError::InvalidFrameAddress(addr),
+    }
+}
+
+/// Maps the `size` bytes of Virtual Memory starting at `start` to newly allocated frames.
+///
+/// The 2 MiB-aligned part of the region is mapped with 2 MiB pages and
+/// the rest of it with 4 KiB pages. Returns the number of mapped pages.
+pub fn map_region_huge<M, F>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    start: VirtAddr,
+    size: usize,
+    flags: PageTableFlags,
+) -> Result<usize, MapToError<Size4KiB>>
+where 
+    M: Mapper<Size4KiB> + Mapper<Size2MiB>,
+    F: FrameAllocator<Size4KiB> + FrameAllocator<Size2MiB>
+     + FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB>,
+{
+    let end = start + size;
+    let huge_start = start.align_up(Size2MiB::SIZE);
+    let huge_end = end.align_down(Size2MiB::SIZE);
+
+    if huge_start >= huge_end {
+        return map_new_pages::<M, F, Size4KiB>(mapper, frame_allocator, start, end, flags);
+    }
+
+    let head = map_new_pages::<M, F, Size4KiB>(mapper, frame_allocator, start, huge_start, flags)?;
+    let huge = map_new_pages::<M, F, Size2MiB>(mapper, frame_allocator, huge_start, huge_end, flags)
+               .map_err(into_4kib_error)?;
+    let tail = map_new_pages::<M, F, Size4KiB>(mapper, frame_allocator, huge_end, end, flags)?;
+
+    Ok(head + huge + tail)
+}
+
+/// Maps the pages of size `S` spanning the region [start, end) to newly allocated frames.
+///
+/// Returns the number of mapped pages.
+fn map_new_pages<M, F, S>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    start: VirtAddr,
+    end: VirtAddr,
+    flags: PageTableFlags,
+) -> Result<usize, MapToError<S>>
+where 
+    S: PageSize,
+    M: Mapper<S>,
+    F: FrameAllocator<S> + FrameAllocator<Size4KiB> + FrameDeallocator<S>,
+{
+    if start >= end {
+        return Ok(0);
+    }
+
+    let start_page = Page::<S>::containing_address(start);
+    let end_page   = Page::<S>::containing_address(end - 1u64);
+    let count = (end_page.start_address() - start_page.start_address()) / S::SIZE + 1;
+
+    map_pages_to_physical_memory(
+        mapper,
+        frame_allocator,
+        Page::range_inclusive(start_page, end_page),
+        flags,
+    )?;
+
+    Ok(count as usize)
+}
+
+fn into_4kib_error(error: MapToError<Size2MiB>) -> MapToError<Size4KiB> {
+    match error {
+        MapToError::FrameAllocationFailed => MapToError::FrameAllocationFailed,
+        MapToError::ParentEntryHugePage   => MapToError::ParentEntryHugePage,
+        MapToError::PageAlreadyMapped(frame) => MapToError::PageAlreadyMapped(
+            PhysFrame::containing_address(frame.start_address())
+        ),
+    }
+}
+
+/// Maps the physical region of `size` bytes starting at `phys_start` to a
+/// free region of Virtual Memory and returns the virtual address of `phys_start`.
+///
+/// The region is extended to whole frames, so its start need not be page-aligned.
+/// Device memory is usually mapped with `NO_CACHE` or `WRITE_THROUGH` in `flags`.
+/// `PRESENT` is always added to `flags`.
+pub fn map_physical_region<M, F>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    phys_start: PhysAddr,
+    size: usize,
+    flags: PageTableFlags,
+) -> Result<VirtAddr, MapToError<Size4KiB>>
 where 
     M: Mapper<Size4KiB>,
     F: FrameAllocator<Size4KiB>,
 {
-    for page in pages {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
+    let flags = flags | PageTableFlags::PRESENT;
+    let start_frame = PhysFrame::containing_address(phys_start);
+    let end_frame = PhysFrame::containing_address(
+        phys_start + (size.max(1) - 1)
+    );
+    let frames = PhysFrame::range_inclusive(start_frame, end_frame);
+
+    let region_size = end_frame.start_address().as_u64()
+                    - start_frame.start_address().as_u64()
+                    + PAGE_SIZE as u64;
+    let region_start = VirtAddr::new(
+        NEXT_PHYSICAL_REGION.fetch_add(region_size, Ordering::Relaxed)
+    );
+    let pages = region_pages(region_start, region_start + (region_size - 1));
+
+    for (page, frame) in pages.zip(frames) {
+        map_page(mapper, frame_allocator, page, frame, flags)?;
+    }
+
+    let offset = phys_start.as_u64() - start_frame.start_address().as_u64();
+
+    Ok(region_start + offset)
+}
+
+fn map_page<M, F, S>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    page: Page<S>,
+    frame: PhysFrame<S>,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<S>>
+where 
+    S: PageSize,
+    M: Mapper<S>,
+    F: FrameAllocator<Size4KiB>,
+{
+    unsafe {
+        let fl = mapper.map_to(page, frame, flags, frame_allocator)?;
+        fl.flush();
+    }
+
+    Ok(())
+}
+
+/// Maps the page at the same virtual address as the given frame to it
+///
+/// Returns `MapToError::PageAlreadyMapped` if that page is mapped already,
+/// even if it is to the same frame.
+/// `PRESENT` is always added to `flags`.
+pub fn identity_map<M, F>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    frame: PhysFrame<Size4KiB>,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<Size4KiB>>
+where
+    M: Mapper<Size4KiB>,
+    F: FrameAllocator<Size4KiB>,
+{
+    unsafe {
+        let fl = mapper.identity_map(frame, flags | PageTableFlags::PRESENT, frame_allocator)?;
+        fl.flush();
+    }
+
+    Ok(())
+}
+
+/// Identity maps the frames from `start` to `end` inclusive, see `identity_map`
+///
+/// The frames mapped before an error are left mapped.
+pub fn identity_map_range<M, F>(
+    mapper: &mut M,
+    frame_allocator: &mut F,
+    start: PhysFrame<Size4KiB>,
+    end: PhysFrame<Size4KiB>,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<Size4KiB>>
+where
+    M: Mapper<Size4KiB>,
+    F: FrameAllocator<Size4KiB>,
+{
+    for frame in PhysFrame::range_inclusive(start, end) {
+        identity_map(mapper, frame_allocator, frame, flags)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the end of the mapped region of the Heap
+pub fn heap_end() -> usize {
+    HEAP_END.load(Ordering::Relaxed)
+}
+
+/// Maps `additional_pages` pages right after the end of the Heap and
+/// returns the number of bytes the Heap region grew by.
+///
+/// It is up to the caller to extend the allocator managing the Heap.
+pub fn grow_heap(additional_pages: usize) -> Result<usize, HeapGrowthError> {
+    let mut memory = KERNEL_MEMORY
+        .try_lock()
+        .ok_or(HeapGrowthError::MemoryUnavailable)?;
+    let memory = memory
+        .as_mut()
+        .ok_or(HeapGrowthError::MemoryUnavailable)?;
+
+    if additional_pages == 0 {
+        return Ok(0);
+    }
+
+    let heap_end = heap_end();
+    let growth = additional_pages * PAGE_SIZE;
+    let [_, guard_page] = heap_guard_pages(HEAP_START, heap_end + growth);
+    if memory.mapper.translate_page(guard_page).is_ok() {
+        return Err(HeapGrowthError::GuardPageMapped);
+    }
+
+    let pages = region_pages(
+        VirtAddr::new(heap_end as u64),
+        VirtAddr::new(
+            (heap_end + growth - 1) as u64
+        ),
+    );
+
+    map_pages_to_physical_memory(
+        &mut memory.mapper,
+        &mut memory.frame_allocator,
+        pages,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+    )
+    .map_err(HeapGrowthError::MappingFailed)?;
+
+    HEAP_END.store(heap_end + growth, Ordering::Relaxed);
+
+    Ok(growth)
+}
+
+/// Unmaps the top `pages` pages of the Heap and deallocates their frames,
+/// provided the global allocator has no block in them.
+///
+/// Returns the number of bytes the Heap region shrank by.
+/// If any of the pages cannot be unmapped, none is and the Heap is left as it was.
+pub fn shrink_heap(pages: usize) -> Result<usize, HeapShrinkError> {
+    let mut memory = KERNEL_MEMORY
+        .try_lock()
+        .ok_or(HeapShrinkError::MemoryUnavailable)?;
+    let memory = memory
+        .as_mut()
+        .ok_or(HeapShrinkError::MemoryUnavailable)?;
 
+    if pages == 0 {
+        return Ok(0);
+    }
+
+    let shrinkage = pages * PAGE_SIZE;
+    if !allocator::release_heap_top(shrinkage) {
+        return Err(HeapShrinkError::HeapInUse);
+    }
+
+    let heap_end = heap_end();
+    let pages = region_pages(
+        VirtAddr::new((heap_end - shrinkage) as u64),
+        VirtAddr::new((heap_end - 1) as u64),
+    );
+    if let Err(error) = try_unmap_pages(&mut memory.mapper, &mut memory.frame_allocator, pages) {
+        // None of the pages was unmapped, so the allocator can take them back
         unsafe {
-            let fl = mapper.map_to(page, frame, flags, frame_allocator)?;
-            fl.flush();
+            allocator::reclaim_heap_top(shrinkage);
         }
+        return Err(HeapShrinkError::UnmappingFailed(error));
+    }
+
+    HEAP_END.store(heap_end - shrinkage, Ordering::Relaxed);
+
+    Ok(shrinkage)
+}
+
+/// Allocates a stack of `pages` pages and returns its top.
+///
+/// The page right below the stack is left unmapped as a guard, so overflowing
+/// the stack causes a page fault instead of corrupting memory.
+/// Panics if `pages` is 0.
+pub fn allocate_stack(pages: usize) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    assert!(pages > 0, "Stacks must have at least one page");
+
+    let guard_page_start = NEXT_STACK.fetch_add(
+        ((pages + 1) * PAGE_SIZE) as u64,
+        Ordering::Relaxed
+    );
+    let stack_start = VirtAddr::new(guard_page_start + PAGE_SIZE as u64);
+    let stack_end = stack_start + pages * PAGE_SIZE;
+
+    with_kernel_memory(|mapper, frame_allocator| {
+        map_pages_to_physical_memory(
+            mapper,
+            frame_allocator,
+            region_pages(stack_start, stack_end - 1u64),
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+        )
+    })?;
+
+    Ok(stack_end)
+}
+
+/// Maps `pages` fresh pages for an allocation of the global allocator and returns their start.
+///
+/// The page right below them is left unmapped as a guard. Returns None if the pages
+/// cannot be mapped or the Kernel memory is in use by the caller, like when allocating
+/// from within `with_kernel_memory`.
+pub(crate) fn map_large_allocation(pages: usize) -> Option<VirtAddr> {
+    let mut memory = KERNEL_MEMORY.try_lock()?;
+    let memory = memory.as_mut()?;
+
+    map_large_allocation_with(&mut memory.mapper, &mut memory.frame_allocator, pages)
+}
+
+/// Maps `pages` fresh pages for a large allocation with the given mapper and frame allocator.
+///
+/// If the pages cannot be mapped, the ones mapped so far are unmapped and their frames
+/// deallocated, and the virtual range is given back so that the next large allocation uses it.
+/// Must be called with the Kernel memory locked, so that no other large allocation is mapped meanwhile.
+fn map_large_allocation_with<M, F>(mapper: &mut M, frame_allocator: &mut F, pages: usize) -> Option<VirtAddr>
+where
+    M: Mapper<Size4KiB>,
+    F: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let guard_page_start = NEXT_LARGE_ALLOCATION.fetch_add(
+        ((pages + 1) * PAGE_SIZE) as u64,
+        Ordering::Relaxed
+    );
+    let start = VirtAddr::new(guard_page_start + PAGE_SIZE as u64);
+
+    let mapped = map_pages_to_physical_memory(
+        mapper,
+        frame_allocator,
+        region_pages(start, start + (pages * PAGE_SIZE - 1)),
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+    );
+
+    match mapped {
+        Ok(()) => Some(start),
+        Err(_) => {
+            // Nothing is left mapped in the range, see `map_pages_to_physical_memory`
+            NEXT_LARGE_ALLOCATION.store(guard_page_start, Ordering::Relaxed);
+            None
+        },
+    }
+}
+
+/// Checks if the address is in the region large allocations are mapped in
+pub(crate) fn is_large_allocation(addr: VirtAddr) -> bool {
+    (LARGE_ALLOCATIONS_START..NEXT_LARGE_ALLOCATION.load(Ordering::Relaxed)).contains(&addr.as_u64())
+}
+
+/// Unmaps the `pages` pages of a large allocation starting at `start` and deallocates their frames.
+///
+/// Returns whether the pages were unmapped. If the Kernel memory is in use by the caller,
+/// the pages are left mapped and thus leaked.
+///
+/// ## Safety
+///
+/// The pages must have been mapped by `map_large_allocation` and must not be used anymore.
+pub(crate) unsafe fn unmap_large_allocation(start: VirtAddr, pages: usize) -> bool {
+    let mut memory = match KERNEL_MEMORY.try_lock() {
+        Some(memory) => memory,
+        None         => return false,
+    };
+
+    match memory.as_mut() {
+        Some(memory) => {
+            let pages = region_pages(start, start + (pages * PAGE_SIZE - 1));
+
+            unmap_pages(&mut memory.mapper, &mut memory.frame_allocator, pages);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Translates the given virtual address to the physical address it is mapped to
+///
+/// Returns None if the address is not mapped.
+pub fn translate_addr(addr: VirtAddr) -> Option<PhysAddr> {
+    with_kernel_memory(|mapper, _| {
+        mapper.translate_addr(addr)
+    })
+}
+
+/// Returns the flags of the entry which maps the given page
+///
+/// For pages within a huge page, those are the flags of the huge page entry.
+/// Returns None if the page is not mapped.
+pub fn page_flags(page: Page<Size4KiB>) -> Option<PageTableFlags> {
+    use x86_64::registers::control::Cr3;
+
+    let (level_4_table_frame, _) = Cr3::read();
+    let indices = [page.p4_index(), page.p3_index(), page.p2_index(), page.p1_index()];
+    let mut table_address = level_4_table_frame.start_address();
+
+    for (level, &index) in indices.iter().enumerate() {
+        let table_ptr: *const PageTable = (phys_mem_offset() + table_address.as_u64()).as_ptr();
+        let entry = unsafe { &(*table_ptr)[index] };
+        let flags = entry.flags();
+
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        if level == indices.len() - 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Some(flags);
+        }
+
+        table_address = entry.addr();
+    }
+
+    None
+}
+
+/// Checks if the page containing the address is mapped, without taking any lock
+///
+/// Returns false if the memory is not initialised yet, so that it can be used
+/// where neither faulting nor waiting is an option, like while panicking.
+pub fn is_mapped(addr: VirtAddr) -> bool {
+    PHYS_MEM_OFFSET.get().is_some() && page_flags(Page::containing_address(addr)).is_some()
+}
+
+/// Replaces the flags of the given mapped page and flushes it from the TLB.
+pub fn update_flags<M>(mapper: &mut M, page: Page<Size4KiB>, flags: PageTableFlags) -> Result<(), FlagUpdateError>
+where
+    M: Mapper<Size4KiB>,
+{
+    unsafe {
+        let fl = mapper.update_flags(page, flags)?;
+        fl.flush();
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Unmaps the given page and flushes it from the TLB.
+///
+/// Returns the frame the page was mapped to. It is not deallocated,
+/// so it is up to the caller to hand it to a frame deallocator.
+pub fn unmap_page<M>(mapper: &mut M, page: Page<Size4KiB>) -> Result<PhysFrame<Size4KiB>, UnmapError>
+where
+    M: Mapper<Size4KiB>,
+{
+    let (frame, fl) = mapper.unmap(page)?;
+    fl.flush();
+
+    Ok(frame)
+}
+
+/// Builds a memory map of the given (start, end, kind) physical regions
+#[cfg(test)]
+pub(crate) fn memory_map_from(regions: &[(u64, u64, bootloader::bootinfo::MemoryRegionType)]) -> MemoryMap {
+    use bootloader::bootinfo::{
+        FrameRange,
+        MemoryRegion,
+    };
+
+    let mut memory_map = MemoryMap::new();
+    for &(start, end, region_type) in regions.iter() {
+        memory_map.add_region(MemoryRegion {
+            range: FrameRange::new(start, end),
+            region_type,
+        });
+    }
+
+    memory_map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page of the Kernel's virtual memory which is not used otherwise
+    fn unused_page() -> Page<Size4KiB> {
+        Page::containing_address(VirtAddr::new(0x_5555_5555_0000))
+    }
+
+    /// A frame allocator handing out only a limited number of frames of another one
+    struct LimitedFrameAllocator<'a> {
+        inner: &'a mut BootInfoFrameAllocator,
+        remaining: usize,
+    }
+
+    unsafe impl FrameAllocator<Size4KiB> for LimitedFrameAllocator<'_> {
+        fn allocate_frame(&mut self) -> Option<PhysFrame> {
+            if self.remaining == 0 {
+                return None;
+            }
+            self.remaining -= 1;
+
+            self.inner.allocate_frame()
+        }
+    }
+
+    impl FrameDeallocator<Size4KiB> for LimitedFrameAllocator<'_> {
+        unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+            self.inner.deallocate_frame(frame);
+        }
+    }
+
+    unsafe impl FrameAllocator<Size2MiB> for LimitedFrameAllocator<'_> {
+        fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+            if self.remaining == 0 {
+                return None;
+            }
+            self.remaining -= 1;
+
+            self.inner.allocate_frame()
+        }
+    }
+
+    impl FrameDeallocator<Size2MiB> for LimitedFrameAllocator<'_> {
+        unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+            self.inner.deallocate_frame(frame);
+        }
+    }
+
+    #[test_case]
+    fn heap_mapping_failure_is_an_error() {
+        let heap_start = unused_page().start_address().as_u64() as usize;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            let mut frame_allocator = LimitedFrameAllocator {
+                inner: frame_allocator,
+                remaining: 0,
+            };
+
+            assert!(matches!(
+                map_heap(mapper, &mut frame_allocator, heap_start, PAGE_SIZE),
+                Err(MemoryInitError::HeapMappingFailed(MapToError::FrameAllocationFailed))
+            ));
+        });
+
+        assert!(translate_addr(unused_page().start_address()).is_none());
+    }
+
+    #[test_case]
+    fn failed_mapping_leaves_no_page_mapped() {
+        let start = VirtAddr::new(0x_5555_5570_0000);
+        let pages = region_pages(start, start + (8 * PAGE_SIZE - 1));
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            // At most 3 frames go to new page tables, so some pages are mapped before failing
+            let mut frame_allocator = LimitedFrameAllocator {
+                inner: frame_allocator,
+                remaining: 5,
+            };
+
+            assert!(matches!(
+                map_pages_to_physical_memory(mapper, &mut frame_allocator, pages, flags),
+                Err(MapToError::FrameAllocationFailed)
+            ));
+        });
+
+        for page in pages {
+            assert!(translate_addr(page.start_address()).is_none());
+        }
+    }
+
+    #[test_case]
+    fn unmapped_page_is_no_longer_translated() {
+        let page = unused_page();
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            map_pages_to_physical_memory(
+                mapper,
+                frame_allocator,
+                Page::range_inclusive(page, page),
+                flags,
+            )
+            .expect("Mapping the page failed");
+
+            unsafe {
+                page.start_address()
+                    .as_mut_ptr::<u64>()
+                    .write_volatile(42);
+            }
+
+            assert!(unmap_page(mapper, page).is_ok());
+            assert!(mapper.translate_addr(page.start_address()).is_none());
+        });
+    }
+
+    #[test_case]
+    fn failed_unmapping_leaves_every_page_mapped() {
+        let page = Page::containing_address(VirtAddr::new(0x_5555_5558_0000));
+        let unmapped = page + 1;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            map_pages_to_physical_memory(
+                mapper,
+                frame_allocator,
+                Page::range_inclusive(page, page),
+                flags,
+            )
+            .expect("Mapping the page failed");
+
+            assert!(matches!(
+                try_unmap_pages(mapper, frame_allocator, Page::range_inclusive(page, unmapped)),
+                Err(UnmapError::PageNotMapped)
+            ));
+            assert!(mapper.translate_addr(page.start_address()).is_some());
+
+            assert!(try_unmap_pages(mapper, frame_allocator, Page::range_inclusive(page, page)).is_ok());
+            assert!(mapper.translate_addr(page.start_address()).is_none());
+        });
+    }
+
+    #[test_case]
+    fn updated_page_flags_are_read_back() {
+        let page = Page::containing_address(VirtAddr::new(0x_5555_5556_0000));
+        let writable = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            map_pages_to_physical_memory(
+                mapper,
+                frame_allocator,
+                Page::range_inclusive(page, page),
+                writable,
+            )
+            .expect("Mapping the page failed");
+
+            assert_eq!(page_flags(page), Some(writable));
+            update_flags(mapper, page, PageTableFlags::PRESENT).expect("Updating the flags failed");
+            assert_eq!(page_flags(page), Some(PageTableFlags::PRESENT));
+
+            unmap_page(mapper, page).expect("Unmapping the page failed");
+            assert_eq!(page_flags(page), None);
+            assert!(update_flags(mapper, page, writable).is_err());
+        });
+    }
+
+    #[test_case]
+    fn identity_mapped_frame_translates_to_itself() {
+        let local_apic = PhysAddr::new(0xfee0_0000);
+        let frame = PhysFrame::containing_address(local_apic);
+        let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            identity_map(mapper, frame_allocator, frame, flags)
+                .expect("Identity mapping the frame failed");
+
+            assert_eq!(mapper.translate_addr(VirtAddr::new(local_apic.as_u64())), Some(local_apic));
+            assert!(matches!(
+                identity_map(mapper, frame_allocator, frame, flags),
+                Err(MapToError::PageAlreadyMapped(_))
+            ));
+
+            let page = Page::containing_address(VirtAddr::new(local_apic.as_u64()));
+            unmap_page(mapper, page).expect("Unmapping the page failed");
+        });
+    }
+
+    #[test_case]
+    fn physical_region_mapped_twice_resolves_to_the_same_frame() {
+        let vga_buffer = PhysAddr::new(0xb8000);
+        let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            let first = map_physical_region(mapper, frame_allocator, vga_buffer, 16, flags)
+                        .expect("Mapping the region failed");
+            let second = map_physical_region(mapper, frame_allocator, vga_buffer, 16, flags)
+                         .expect("Mapping the region failed");
+
+            assert!(first != second);
+            assert_eq!(mapper.translate_addr(first), Some(vga_buffer));
+            assert_eq!(mapper.translate_addr(second), Some(vga_buffer));
+        });
+    }
+
+    #[test_case]
+    fn huge_page_aligned_region_is_mapped_with_fewer_pages() {
+        let start = VirtAddr::new(0x_7777_7760_0000);
+        let huge_page_size = Size2MiB::SIZE as usize;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        with_kernel_memory(|mapper, frame_allocator| {
+            let mapped_pages = map_region_huge(
+                mapper,
+                frame_allocator,
+                start,
+                huge_page_size + PAGE_SIZE,
+                flags,
+            )
+            .expect("Mapping the region failed");
+
+            assert_eq!(mapped_pages, 2);
+            assert!(mapper.translate_addr(start + huge_page_size).is_some());
+        });
+    }
+
+    #[test_case]
+    fn phys_mem_offset_maps_the_physical_memory() {
+        use x86_64::registers::control::Cr3;
+
+        let (level_4_table_frame, _) = Cr3::read();
+        let phys = level_4_table_frame.start_address();
+
+        assert!(phys_mem_offset().as_u64() != 0);
+        assert_eq!(translate_addr(phys_mem_offset() + phys.as_u64()), Some(phys));
+    }
+
+    #[test_case]
+    fn bytes_written_to_a_mapped_frame_are_read_back() {
+        let frame: PhysFrame = with_kernel_memory(|_, frame_allocator| {
+            FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator).expect("No frames left")
+        });
+        let pattern = |i: usize| (i % 251) as u8;
+
+        // The frame was just allocated, so nothing else uses it
+        let matches = unsafe {
+            with_mapped_frame(frame, |bytes| {
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = pattern(i);
+                }
+            });
+            with_mapped_frame(frame, |bytes| {
+                bytes.iter()
+                     .enumerate()
+                     .all(|(i, &byte)| byte == pattern(i))
+            })
+        };
+
+        with_kernel_memory(|_, frame_allocator| unsafe {
+            FrameDeallocator::<Size4KiB>::deallocate_frame(frame_allocator, frame);
+        });
+        assert!(matches);
+    }
+
+    #[test_case]
+    fn failed_large_allocation_leaves_nothing_mapped() {
+        with_kernel_memory(|mapper, frame_allocator| {
+            let next = NEXT_LARGE_ALLOCATION.load(Ordering::Relaxed);
+            let start = VirtAddr::new(next + PAGE_SIZE as u64);
+            let mut frame_allocator = LimitedFrameAllocator {
+                inner: frame_allocator,
+                remaining: 5,
+            };
+
+            assert!(map_large_allocation_with(mapper, &mut frame_allocator, 8).is_none());
+            assert_eq!(NEXT_LARGE_ALLOCATION.load(Ordering::Relaxed), next);
+            for page in region_pages(start, start + (8 * PAGE_SIZE - 1)) {
+                assert!(mapper.translate_page(page).is_err());
+            }
+        });
+    }
+
+    #[test_case]
+    fn allocated_stack_is_mapped_above_an_unmapped_guard_page() {
+        let pages = 4;
+        let top = allocate_stack(pages).expect("Allocating the stack failed");
+        let bottom = top - (pages * PAGE_SIZE) as u64;
+
+        unsafe {
+            (top - 8u64).as_mut_ptr::<u64>().write_volatile(1);
+            bottom.as_mut_ptr::<u64>().write_volatile(2);
+        }
+
+        assert!(translate_addr(bottom - 1u64).is_none());
+    }
+
+    fn memory_map_with_usable_bytes(usable: u64) -> MemoryMap {
+        use bootloader::bootinfo::MemoryRegionType;
+
+        memory_map_from(&[
+            (0, 0x1000, MemoryRegionType::FrameZero),
+            (0x10_0000, 0x10_0000 + usable, MemoryRegionType::Usable),
+        ])
+    }
+
+    #[test_case]
+    fn recommended_heap_size_is_a_share_of_small_usable_memory() {
+        let memory_map = memory_map_with_usable_bytes(256 * 1024 + 0x1000);
+
+        assert_eq!(heap_size_for(&memory_map), 64 * 1024);
+    }
+
+    #[test_case]
+    fn recommended_heap_size_is_capped_for_large_usable_memory() {
+        let memory_map = memory_map_with_usable_bytes(1 << 30);
+
+        assert_eq!(heap_size_for(&memory_map), MAX_HEAP_SIZE);
+    }
+
+    #[test_case]
+    fn recommended_heap_size_covers_whole_pages() {
+        let memory_map = memory_map_with_usable_bytes(4 * MIN_HEAP_SIZE as u64 + 0x7000);
+
+        assert_eq!(heap_size_for(&memory_map), MIN_HEAP_SIZE + 0x1000);
+    }
+
+    #[test_case]
+    fn recommended_heap_size_is_never_below_the_minimum() {
+        assert_eq!(heap_size_for(&memory_map_with_usable_bytes(0)), MIN_HEAP_SIZE);
+        assert_eq!(heap_size_for(&memory_map_with_usable_bytes(5 * 0x1000)), MIN_HEAP_SIZE);
+    }
+
+    #[test_case]
+    fn heap_start_is_translated() {
+        let heap_start = VirtAddr::new(HEAP_START as u64);
+
+        assert!(translate_addr(heap_start).is_some());
+    }
+
+    #[test_case]
+    fn pages_around_the_heap_are_unmapped() {
+        let below_heap = VirtAddr::new((HEAP_START - PAGE_SIZE) as u64);
+        let after_heap = VirtAddr::new(heap_end() as u64);
+
+        assert!(translate_addr(below_heap).is_none());
+        assert!(translate_addr(after_heap).is_none());
+    }
+
+    #[test_case]
+    fn shrinking_the_heap_returns_its_frames() {
+        let pages = 4;
+        allocator::grow_heap(pages).expect("Growing the Heap failed");
+
+        let top_page = VirtAddr::new((heap_end() - PAGE_SIZE) as u64);
+        let top_frame = translate_addr(top_page).map(PhysFrame::containing_address);
+        // Aligned past the page size, so that the block is on the Heap rather than on pages of its own
+        let layout = alloc::alloc::Layout::from_size_align(pages * PAGE_SIZE / 2, 2 * PAGE_SIZE).unwrap();
+        unsafe {
+            let block = alloc::alloc::alloc(layout);
+            assert!(!block.is_null());
+            block.write_bytes(0, layout.size());
+            alloc::alloc::dealloc(block, layout);
+        }
+
+        assert!(matches!(shrink_heap(pages), Ok(shrinkage) if shrinkage == pages * PAGE_SIZE));
+        assert!(translate_addr(top_page).is_none());
+        with_kernel_memory(|_, frame_allocator| {
+            let frame: Option<PhysFrame> = frame_allocator.allocate_frame();
+            assert_eq!(frame, top_frame);
+            unsafe {
+                frame_allocator.deallocate_frame(frame.unwrap());
+            }
+        });
+    }
+
+    #[test_case]
+    fn unmapped_address_is_not_translated() {
+        assert!(translate_addr(unused_page().start_address()).is_none());
+        assert!(!is_mapped(unused_page().start_address()));
+        assert!(is_mapped(VirtAddr::new(HEAP_START as u64)));
+    }
+
+    #[test_case]
+    fn unmapping_an_unmapped_page_fails() {
+        with_kernel_memory(|mapper, _| {
+            assert!(matches!(
+                unmap_page(mapper, unused_page()),
+                Err(UnmapError::PageNotMapped)
+            ));
+        });
+    }
+}
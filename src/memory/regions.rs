@@ -0,0 +1,82 @@
+use bootloader::bootinfo::{
+    MemoryMap,
+    MemoryRegionType,
+};
+use x86_64::PhysAddr;
+
+/// A region of physical memory described by the bootloader's memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysRegion {
+    pub start: PhysAddr,
+    /// The address right after the region
+    pub end: PhysAddr,
+    pub kind: MemoryRegionType,
+}
+
+impl PhysRegion {
+    /// The size of the region in bytes
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_usable(&self) -> bool {
+        self.kind == MemoryRegionType::Usable
+    }
+}
+
+/// Returns the regions of the memory map, usually `boot_info.memory_map`, in order
+pub fn all_regions(memory_map: &MemoryMap) -> impl Iterator<Item = PhysRegion> + '_ {
+    memory_map.iter()
+              .map(|region| PhysRegion {
+                  start: PhysAddr::new(region.range.start_addr()),
+                  end: PhysAddr::new(region.range.end_addr()),
+                  kind: region.region_type,
+              })
+}
+
+/// Returns the regions of the memory map which are free to use, in order
+///
+/// They are page-aligned by the bootloader.
+pub fn usable_regions(memory_map: &MemoryMap) -> impl Iterator<Item = PhysRegion> + '_ {
+    all_regions(memory_map).filter(PhysRegion::is_usable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::memory_map_from;
+    use alloc::vec::Vec;
+
+    fn region(start: u64, end: u64, kind: MemoryRegionType) -> PhysRegion {
+        PhysRegion {
+            start: PhysAddr::new(start),
+            end: PhysAddr::new(end),
+            kind,
+        }
+    }
+
+    #[test_case]
+    fn regions_of_the_memory_map_are_yielded_with_their_kinds() {
+        let regions = [
+            region(0x0000, 0x1000, MemoryRegionType::FrameZero),
+            region(0x1000, 0x9000, MemoryRegionType::Usable),
+            region(0x9000, 0xa000, MemoryRegionType::Bootloader),
+            region(0x10_0000, 0x20_0000, MemoryRegionType::Usable),
+            region(0x20_0000, 0x20_4000, MemoryRegionType::Reserved),
+        ];
+        let memory_map = memory_map_from(&[
+            (0x0000, 0x1000, MemoryRegionType::FrameZero),
+            (0x1000, 0x9000, MemoryRegionType::Usable),
+            (0x9000, 0xa000, MemoryRegionType::Bootloader),
+            (0x10_0000, 0x20_0000, MemoryRegionType::Usable),
+            (0x20_0000, 0x20_4000, MemoryRegionType::Reserved),
+        ]);
+
+        let all: Vec<_> = all_regions(&memory_map).collect();
+        let usable: Vec<_> = usable_regions(&memory_map).collect();
+
+        assert_eq!(all, regions);
+        assert_eq!(usable, [regions[1], regions[3]]);
+        assert_eq!(usable.iter().map(PhysRegion::size).sum::<u64>(), 0x8000 + 0x10_0000);
+    }
+}
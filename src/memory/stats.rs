@@ -0,0 +1,101 @@
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+use bootloader::bootinfo::MemoryMap;
+use crate::allocator;
+
+static TOTAL_PHYSICAL: AtomicUsize = AtomicUsize::new(0);
+static USABLE_PHYSICAL: AtomicUsize = AtomicUsize::new(0);
+
+/// A snapshot of the memory usage, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub heap_size: usize,
+    pub heap_used: usize,
+    pub heap_free: usize,
+    pub total_physical: usize,
+    pub usable_physical: usize,
+}
+
+/// Returns the current memory usage
+pub fn stats() -> MemoryStats {
+    let (heap_size, heap_used) = allocator::heap_usage();
+
+    MemoryStats {
+        heap_size,
+        heap_used,
+        heap_free: heap_size - heap_used,
+        total_physical: TOTAL_PHYSICAL.load(Ordering::Relaxed),
+        usable_physical: USABLE_PHYSICAL.load(Ordering::Relaxed),
+    }
+}
+
+/// Records the amount of physical memory described by the memory map
+pub(super) fn record_physical_memory(memory_map: &MemoryMap) {
+    let (total, usable) = physical_memory_sizes(memory_map);
+
+    TOTAL_PHYSICAL.store(total, Ordering::Relaxed);
+    USABLE_PHYSICAL.store(usable, Ordering::Relaxed);
+}
+
+/// Returns the total and the usable bytes of physical memory in the memory map
+pub(crate) fn physical_memory_sizes(memory_map: &MemoryMap) -> (usize, usize) {
+    super::all_regions(memory_map)
+    .fold((0, 0), |(total, usable), region| {
+        let size = region.size() as usize;
+
+        if region.is_usable() {
+            (total + size, usable + size)
+        } else {
+            (total + size, usable)
+        }
+    })
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "heap: {} of {} bytes used, {} bytes free",
+            self.heap_used,
+            self.heap_size,
+            self.heap_free,
+        )?;
+        write!(
+            f,
+            "physical memory: {} of {} bytes usable",
+            self.usable_physical,
+            self.total_physical,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test_case]
+    fn allocations_increase_heap_used() {
+        let size = 4096;
+
+        let before = stats().heap_used;
+        let buffer: Vec<u8> = Vec::with_capacity(size);
+        let after = stats().heap_used;
+
+        assert!(after - before >= size);
+        drop(buffer);
+    }
+
+    #[test_case]
+    fn usable_physical_memory_is_recorded() {
+        let stats = stats();
+
+        assert!(stats.usable_physical > 0);
+        assert!(stats.usable_physical <= stats.total_physical);
+    }
+}
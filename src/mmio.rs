@@ -0,0 +1,111 @@
+use core::{
+    marker::PhantomData,
+    ops::Range,
+    ptr,
+};
+use x86_64::VirtAddr;
+
+/// A memory-mapped device register of type `T`
+///
+/// Each read and write is a volatile access, which the compiler neither
+/// removes nor reorders with other volatile accesses.
+pub struct Mmio<T> {
+    address: *mut T,
+    _register: PhantomData<T>,
+}
+
+impl<T: Copy> Mmio<T> {
+    /// Creates an accessor of the register at `address`
+    ///
+    /// ## Safety
+    ///
+    /// The caller must guarantee that `address` is mapped to the register, suitably
+    /// aligned for `T`. Device memory must be mapped with `NO_CACHE`, so that
+    /// accesses reach the device instead of the cache.
+    pub unsafe fn new(address: VirtAddr) -> Self {
+        Mmio {
+            address: address.as_mut_ptr(),
+            _register: PhantomData,
+        }
+    }
+
+    pub fn read(&self) -> T {
+        unsafe { ptr::read_volatile(self.address) }
+    }
+
+    pub fn write(&mut self, value: T) {
+        unsafe { ptr::write_volatile(self.address, value) }
+    }
+}
+
+macro_rules! bit_fields {
+    ($($int:ty),*) => {
+        $(
+            impl Mmio<$int> {
+                /// Reads the value of the given range of bits
+                ///
+                /// Panics if the range is empty or exceeds the register.
+                pub fn get_bits(&self, bits: Range<u32>) -> $int {
+                    let mask = bits_mask::<$int>(&bits);
+
+                    (self.read() & mask) >> bits.start
+                }
+
+                /// Writes `value` to the given range of bits, keeping the other ones
+                ///
+                /// The bits of `value` which do not fit in the range are ignored.
+                /// Panics if the range is empty or exceeds the register.
+                pub fn set_bits(&mut self, bits: Range<u32>, value: $int) {
+                    let mask = bits_mask::<$int>(&bits);
+                    let old = self.read();
+
+                    self.write((old & !mask) | ((value << bits.start) & mask));
+                }
+            }
+        )*
+    };
+}
+
+bit_fields!(u8, u16, u32, u64);
+
+/// A mask with the given range of bits of an integer of type `T` set
+fn bits_mask<T>(bits: &Range<u32>) -> T
+where
+    T: From<u8> + core::ops::Not<Output = T> + core::ops::Shl<u32, Output = T> + core::ops::Shr<u32, Output = T>,
+{
+    let width = (core::mem::size_of::<T>() * 8) as u32;
+    assert!(bits.start < bits.end && bits.end <= width, "Invalid bit range {:?}", bits);
+
+    let ones = !T::from(0);
+
+    (ones >> (width - (bits.end - bits.start))) << bits.start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn written_value_is_read_back() {
+        let mut cell: u32 = 0;
+        let mut register = unsafe { Mmio::new(VirtAddr::from_ptr(&mut cell)) };
+
+        register.write(0xdead_beef);
+
+        assert_eq!(register.read(), 0xdead_beef);
+        assert_eq!(unsafe { ptr::read_volatile(&cell) }, 0xdead_beef);
+    }
+
+    #[test_case]
+    fn bit_fields_are_set_and_read_in_place() {
+        let mut cell: u32 = 0xffff_0000;
+        let mut register = unsafe { Mmio::new(VirtAddr::from_ptr(&mut cell)) };
+
+        register.set_bits(4..12, 0xab);
+        register.set_bits(28..32, 0x1);
+
+        assert_eq!(register.read(), 0x1fff_0ab0);
+        assert_eq!(register.get_bits(4..12), 0xab);
+        assert_eq!(register.get_bits(0..32), 0x1fff_0ab0);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{
+    hlt_loop,
+    power,
+    println,
+    rtc,
+    serial_println,
+    time,
+};
+
+/// The number of consecutive panics after each of which the machine is rebooted.
+/// It is halted on the next one, so that a kernel panicking on boot does not reboot forever.
+pub const MAX_PANIC_REBOOTS: u8 = 3;
+
+/// The uptime after which a boot counts as successful and the panic count is reset
+pub const STABLE_UPTIME_MS: u64 = 10_000;
+
+/// The CMOS register holding the number of consecutive panics, which survives reboots.
+/// It is past the registers the BIOS uses and outside of their checksum.
+const PANIC_COUNT_REGISTER: u8 = 0x7e;
+
+/// What the panic handler does after reporting a panic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicAction {
+    Reboot,
+    Halt,
+}
+
+/// The action to take on the `panic_count`-th consecutive panic
+pub fn action_for(panic_count: u8) -> PanicAction {
+    if panic_count <= MAX_PANIC_REBOOTS {
+        PanicAction::Reboot
+    } else {
+        PanicAction::Halt
+    }
+}
+
+/// Counts the panic and reboots, or halts once there were too many consecutive ones
+///
+/// It is meant to be called by the panic handler after reporting the panic, with interrupts disabled.
+pub fn reboot_or_halt() -> ! {
+    let panic_count = rtc::read_register(PANIC_COUNT_REGISTER).saturating_add(1);
+    rtc::write_register(PANIC_COUNT_REGISTER, panic_count);
+
+    match action_for(panic_count) {
+        PanicAction::Reboot => {
+            println!("Rebooting after panic {} of at most {}", panic_count, MAX_PANIC_REBOOTS);
+            serial_println!("Rebooting after panic {} of at most {}", panic_count, MAX_PANIC_REBOOTS);
+            power::reboot();
+        },
+        PanicAction::Halt => {
+            println!("{} consecutive panics, halting instead of rebooting", panic_count);
+            serial_println!("{} consecutive panics, halting instead of rebooting", panic_count);
+            hlt_loop();
+        },
+    }
+}
+
+/// Resets the panic count once the kernel has been up for `STABLE_UPTIME_MS`
+pub fn reset_after_stable_uptime() {
+    let elapsed = time::uptime_ms();
+
+    // the only timeout set during boot, so there is always room for it
+    let _ = time::set_timeout(STABLE_UPTIME_MS.saturating_sub(elapsed), reset_panic_count);
+}
+
+fn reset_panic_count() {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| rtc::write_register(PANIC_COUNT_REGISTER, 0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn machine_is_rebooted_until_the_threshold_is_exceeded() {
+        assert_eq!(action_for(1), PanicAction::Reboot);
+        assert_eq!(action_for(MAX_PANIC_REBOOTS), PanicAction::Reboot);
+        assert_eq!(action_for(MAX_PANIC_REBOOTS + 1), PanicAction::Halt);
+        assert_eq!(action_for(u8::MAX), PanicAction::Halt);
+    }
+}
@@ -0,0 +1,93 @@
+use x86_64::instructions::port::Port;
+#[cfg(test)]
+use alloc::{
+    collections::VecDeque,
+    vec::Vec,
+};
+
+/// The port I/O device drivers are performed with, so that tests can record it
+pub(crate) trait PortIo {
+    fn read_u8(&mut self, port: u16) -> u8;
+    fn write_u8(&mut self, port: u16, value: u8);
+    fn write_u16(&mut self, port: u16, value: u16);
+}
+
+/// The I/O ports of the machine
+pub(crate) struct MachinePorts;
+
+impl PortIo for MachinePorts {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        unsafe { Port::new(port).read() }
+    }
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        unsafe {
+            Port::new(port).write(value);
+        }
+    }
+
+    fn write_u16(&mut self, port: u16, value: u16) {
+        unsafe {
+            Port::new(port).write(value);
+        }
+    }
+}
+
+/// Records the (port, value) writes and answers reads with queued values, or 0 once they run out
+#[cfg(test)]
+pub(crate) struct RecordingPorts {
+    pub writes: Vec<(u16, u16)>,
+    read_counts: Vec<(u16, usize)>,
+    queued_reads: Vec<(u16, VecDeque<u8>)>,
+}
+
+#[cfg(test)]
+impl RecordingPorts {
+    pub fn new() -> Self {
+        RecordingPorts {
+            writes: Vec::new(),
+            read_counts: Vec::new(),
+            queued_reads: Vec::new(),
+        }
+    }
+
+    /// Queues `values` to be returned by the next reads of `port`, after the ones queued already
+    pub fn queue_reads(&mut self, port: u16, values: &[u8]) {
+        match self.queued_reads.iter_mut().find(|(p, _)| *p == port) {
+            Some((_, queue)) => queue.extend(values),
+            None             => self.queued_reads.push((port, values.iter().copied().collect())),
+        }
+    }
+
+    /// The number of times `port` was read
+    pub fn reads(&self, port: u16) -> usize {
+        self.read_counts
+            .iter()
+            .find(|(p, _)| *p == port)
+            .map_or(0, |&(_, count)| count)
+    }
+}
+
+#[cfg(test)]
+impl PortIo for RecordingPorts {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        match self.read_counts.iter_mut().find(|(p, _)| *p == port) {
+            Some((_, count)) => *count += 1,
+            None             => self.read_counts.push((port, 1)),
+        }
+
+        self.queued_reads
+            .iter_mut()
+            .find(|(p, _)| *p == port)
+            .and_then(|(_, queue)| queue.pop_front())
+            .unwrap_or(0)
+    }
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        self.writes.push((port, u16::from(value)));
+    }
+
+    fn write_u16(&mut self, port: u16, value: u16) {
+        self.writes.push((port, value));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{
+    hlt_loop,
+    port_io::{
+        MachinePorts,
+        PortIo,
+    },
+    ps2,
+};
+
+/// The (port, value) writes which power off the emulators supporting them,
+/// starting with QEMU, followed by Bochs and older QEMU versions, and VirtualBox
+const SHUTDOWN_WRITES: [(u16, u16); 3] = [
+    (0x604, 0x2000),
+    (0xB004, 0x2000),
+    (0x4004, 0x3400),
+];
+
+/// Powers off the machine
+///
+/// Halts if none of the power-off mechanisms takes effect.
+pub fn shutdown() -> ! {
+    write_shutdown(&mut MachinePorts);
+
+    hlt_loop();
+}
+
+/// Resets the machine by pulsing the CPU reset line through the PS/2 controller
+///
+/// Halts if the reset does not take effect.
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    write_reset(&mut MachinePorts);
+
+    hlt_loop();
+}
+
+/// Performs the writes of each power-off mechanism in turn
+fn write_shutdown(ports: &mut impl PortIo) {
+    for &(port, value) in SHUTDOWN_WRITES.iter() {
+        ports.write_u16(port, value);
+    }
+}
+
+/// Waits for the PS/2 controller to accept a command and sends it the reset command
+fn write_reset(ports: &mut impl PortIo) {
+    while ports.read_u8(ps2::STATUS_PORT) & ps2::INPUT_BUFFER_FULL != 0 {}
+
+    ports.write_u8(ps2::COMMAND_PORT, ps2::PULSE_RESET_LINE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::port_io::RecordingPorts;
+
+    #[test_case]
+    fn shutdown_tries_qemu_first() {
+        let mut ports = RecordingPorts::new();
+        write_shutdown(&mut ports);
+
+        assert_eq!(ports.writes, [(0x604, 0x2000), (0xb004, 0x2000), (0x4004, 0x3400)]);
+        assert_eq!(ports.reads(0x64), 0);
+    }
+
+    #[test_case]
+    fn reboot_waits_for_the_controller_before_pulsing_the_reset_line() {
+        let mut ports = RecordingPorts::new();
+        ports.queue_reads(0x64, &[ps2::INPUT_BUFFER_FULL, ps2::INPUT_BUFFER_FULL]);
+        write_reset(&mut ports);
+
+        assert_eq!(ports.reads(0x64), 3);
+        assert_eq!(ports.writes, [(0x64, 0xfe)]);
+    }
+}
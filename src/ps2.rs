@@ -0,0 +1,12 @@
+pub(crate) const DATA_PORT: u16 = 0x60;
+pub(crate) const STATUS_PORT: u16 = 0x64;
+pub(crate) const COMMAND_PORT: u16 = 0x64;
+
+/// The status bit set while a byte from the keyboard waits to be read
+pub(crate) const OUTPUT_BUFFER_FULL: u8 = 0b01;
+
+/// The status bit set while the controller has not yet taken the last written byte
+pub(crate) const INPUT_BUFFER_FULL: u8 = 0b10;
+
+/// The controller command which pulses the CPU reset line
+pub(crate) const PULSE_RESET_LINE: u8 = 0xfe;
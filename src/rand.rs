@@ -0,0 +1,92 @@
+use core::arch::x86_64::{
+    _rdrand64_step,
+    _rdtsc,
+};
+use crate::cpu::{
+    self,
+    CpuFeature,
+};
+
+/// The number of times RDRAND is retried before giving up
+const RDRAND_RETRIES: usize = 10;
+
+/// The state a `XorShift64` seeded with 0 starts from, as 0 is a fixed point
+const DEFAULT_XORSHIFT_STATE: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Returns a random number from the CPU's hardware generator
+///
+/// Returns None if the CPU lacks RDRAND or it repeatedly failed to produce a number.
+pub fn rdrand_u64() -> Option<u64> {
+    if !cpu::has_feature(CpuFeature::Rdrand) {
+        return None;
+    }
+
+    for _ in 0..RDRAND_RETRIES {
+        let mut value = 0;
+        if unsafe { _rdrand64_step(&mut value) } == 1 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// A fast non-cryptographic pseudo-random number generator
+pub struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    pub fn new(seed: u64) -> Self {
+        XorShift64 {
+            state: if seed == 0 { DEFAULT_XORSHIFT_STATE } else { seed },
+        }
+    }
+
+    /// Creates a generator seeded from RDRAND,
+    /// or from the time stamp counter if RDRAND is unavailable
+    pub fn from_hardware() -> Self {
+        let seed = rdrand_u64().unwrap_or_else(|| unsafe { _rdtsc() });
+
+        Self::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn consecutive_rdrand_numbers_differ() {
+        if let (Some(first), Some(second)) = (rdrand_u64(), rdrand_u64()) {
+            assert!(first != second);
+        }
+    }
+
+    #[test_case]
+    fn xorshift_is_deterministic_for_a_seed() {
+        let mut first = XorShift64::new(42);
+        let mut second = XorShift64::new(42);
+
+        for _ in 0..10 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+    }
+
+    #[test_case]
+    fn xorshift_seeded_with_zero_does_not_get_stuck() {
+        let mut generator = XorShift64::new(0);
+
+        assert!(generator.next_u64() != 0);
+    }
+}
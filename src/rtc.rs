@@ -0,0 +1,178 @@
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+
+const SECONDS_REGISTER: u8 = 0x00;
+const MINUTES_REGISTER: u8 = 0x02;
+const HOURS_REGISTER: u8 = 0x04;
+const DAY_REGISTER: u8 = 0x07;
+const MONTH_REGISTER: u8 = 0x08;
+const YEAR_REGISTER: u8 = 0x09;
+const STATUS_A_REGISTER: u8 = 0x0A;
+const STATUS_B_REGISTER: u8 = 0x0B;
+
+/// The status A bit set while the RTC is updating its registers
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+
+/// The status B bit set when the registers hold binary instead of BCD values
+const BINARY_MODE: u8 = 0x04;
+
+/// The status B bit set when the hours are in 24-hour format
+const HOUR_24_MODE: u8 = 0x02;
+
+/// The hours bit set for PM hours in 12-hour format
+const PM_HOUR: u8 = 0x80;
+
+/// The years the RTC's two-digit years are relative to
+const CENTURY: u16 = 2000;
+
+/// A wall-clock date and time
+///
+/// Comparing date-times orders them chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// The raw values of the RTC registers
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Registers {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+/// Reads the current date and time from the CMOS real-time clock
+pub fn read() -> DateTime {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut registers = read_registers();
+        loop {
+            let again = read_registers();
+            if again == registers {
+                break;
+            }
+            registers = again;
+        }
+
+        decode(registers, read_register(STATUS_B_REGISTER))
+    })
+}
+
+/// Reads the time registers once no update is in progress
+///
+/// An update can still begin while reading, so the result should be confirmed by a second read.
+fn read_registers() -> Registers {
+    while read_register(STATUS_A_REGISTER) & UPDATE_IN_PROGRESS != 0 {}
+
+    Registers {
+        second: read_register(SECONDS_REGISTER),
+        minute: read_register(MINUTES_REGISTER),
+        hour: read_register(HOURS_REGISTER),
+        day: read_register(DAY_REGISTER),
+        month: read_register(MONTH_REGISTER),
+        year: read_register(YEAR_REGISTER),
+    }
+}
+
+/// Reads a register of the CMOS memory
+///
+/// It should be called with interrupts disabled, so that the register is not changed in between.
+pub(crate) fn read_register(register: u8) -> u8 {
+    let mut address: Port<u8> = Port::new(CMOS_ADDRESS_PORT);
+    let mut data: Port<u8> = Port::new(CMOS_DATA_PORT);
+
+    unsafe {
+        address.write(register);
+        data.read()
+    }
+}
+
+/// Writes a register of the CMOS memory, see `read_register`
+pub(crate) fn write_register(register: u8, value: u8) {
+    let mut address: Port<u8> = Port::new(CMOS_ADDRESS_PORT);
+    let mut data: Port<u8> = Port::new(CMOS_DATA_PORT);
+
+    unsafe {
+        address.write(register);
+        data.write(value);
+    }
+}
+
+/// Converts the raw register values to a date-time according to the format in status B
+fn decode(registers: Registers, status_b: u8) -> DateTime {
+    let to_binary = |value: u8| {
+        if status_b & BINARY_MODE != 0 {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0x0f)
+        }
+    };
+
+    let is_pm = registers.hour & PM_HOUR != 0;
+    let mut hour = to_binary(registers.hour & !PM_HOUR);
+    if status_b & HOUR_24_MODE == 0 {
+        hour %= 12;
+        if is_pm {
+            hour += 12;
+        }
+    }
+
+    DateTime {
+        year: CENTURY + u16::from(to_binary(registers.year)),
+        month: to_binary(registers.month),
+        day: to_binary(registers.day),
+        hour,
+        minute: to_binary(registers.minute),
+        second: to_binary(registers.second),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+
+    #[test_case]
+    fn later_reading_is_not_earlier() {
+        let first = read();
+        time::sleep_ms(1000);
+        let second = read();
+
+        assert!(second >= first);
+    }
+
+    #[test_case]
+    fn bcd_12_hour_registers_are_decoded() {
+        let registers = Registers {
+            second: 0x59,
+            minute: 0x30,
+            hour: PM_HOUR | 0x11,
+            day: 0x25,
+            month: 0x12,
+            year: 0x20,
+        };
+
+        assert_eq!(
+            decode(registers, 0),
+            DateTime {
+                year: 2020,
+                month: 12,
+                day: 25,
+                hour: 23,
+                minute: 30,
+                second: 59,
+            }
+        );
+    }
+}
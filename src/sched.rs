@@ -0,0 +1,300 @@
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+};
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use x86_64::{
+    VirtAddr,
+    structures::paging::{
+        Size4KiB,
+        mapper::MapToError,
+    },
+};
+use crate::memory;
+
+/// The number of pages in the stack of each spawned thread
+pub const THREAD_STACK_PAGES: usize = 8;
+
+/// The number of callee-saved registers pushed by `context_switch`
+const SAVED_REGISTERS: usize = 6;
+
+/// The value at the bottom of each thread stack, which is overwritten when the stack nearly overflows
+const STACK_CANARY: u64 = 0xdead_c0de_f00d_cafe;
+
+static SCHEDULER: spin::Mutex<Option<Scheduler>> = spin::Mutex::new(None);
+
+/// A unique identifier of a thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A kernel thread with its own stack
+///
+/// While the thread is not running, its callee-saved registers
+/// are stored on its stack and `rsp` points to them.
+pub struct Thread {
+    id: ThreadId,
+    rsp: u64,
+    stack_top: Option<VirtAddr>,
+}
+
+impl Thread {
+    /// Represents the code which was running before the first thread was spawned
+    fn boot() -> Self {
+        Thread {
+            id: ThreadId::new(),
+            rsp: 0,
+            stack_top: None,
+        }
+    }
+
+    /// Creates a thread which starts running `entry` on a fresh stack once switched to
+    fn new(entry: fn()) -> Result<Self, MapToError<Size4KiB>> {
+        let stack_top = memory::allocate_stack(THREAD_STACK_PAGES)?;
+
+        // The initial stack mimics the one left by context_switch:
+        // the saved registers, with the entry in r12, and a return address to the trampoline.
+        let rsp = stack_top - ((SAVED_REGISTERS + 1) * 8) as u64;
+        let frame = rsp.as_mut_ptr::<u64>();
+        unsafe {
+            frame.write_bytes(0, SAVED_REGISTERS);
+            frame.add(3).write(entry as usize as u64);
+            frame.add(SAVED_REGISTERS).write(thread_trampoline as usize as u64);
+            stack_canary(stack_top).write(STACK_CANARY);
+        }
+
+        Ok(Thread {
+            id: ThreadId::new(),
+            rsp: rsp.as_u64(),
+            stack_top: Some(stack_top),
+        })
+    }
+
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// The top of the thread's stack, `None` for the boot thread
+    pub fn stack_top(&self) -> Option<VirtAddr> {
+        self.stack_top
+    }
+
+    /// Checks if the canary at the bottom of the thread's stack is unchanged
+    ///
+    /// The boot thread has no canary, so its stack always counts as intact.
+    pub fn is_stack_intact(&self) -> bool {
+        self.stack_top.map_or(true, |stack_top| unsafe {
+            stack_canary(stack_top).read_volatile() == STACK_CANARY
+        })
+    }
+}
+
+/// The location of the canary of the thread stack with the given top
+fn stack_canary(stack_top: VirtAddr) -> *mut u64 {
+    (stack_top - (THREAD_STACK_PAGES * memory::PAGE_SIZE) as u64).as_mut_ptr()
+}
+
+/// Panics if the thread overwrote the canary at the bottom of its stack
+///
+/// Called for the thread being switched from on each context switch.
+pub fn check_stack_canary(thread: &Thread) {
+    assert!(thread.is_stack_intact(), "The stack of thread {:?} overflowed its canary", thread.id);
+}
+
+struct Scheduler {
+    current: Box<Thread>,
+    ready: VecDeque<Box<Thread>>,
+}
+
+impl Scheduler {
+    /// Makes the first ready thread current and queues the previous one if `requeue` is set.
+    ///
+    /// Returns where to save the previous thread's `rsp` and the `rsp` to switch to,
+    /// or None if no other thread is ready.
+    fn switch_to_next(&mut self, requeue: bool) -> Option<(*mut u64, u64)> {
+        let next = self.ready.pop_front()?;
+        check_stack_canary(&self.current);
+        let mut previous = core::mem::replace(&mut self.current, next);
+        let previous_rsp = &mut previous.rsp as *mut u64;
+
+        // The box keeps the thread in place, so the pointer stays valid once queued.
+        if requeue {
+            self.ready.push_back(previous);
+        }
+        else {
+            // The stack of a finished thread is not freed, it is only abandoned,
+            // so its saved rsp can be written there.
+            Box::leak(previous);
+        }
+
+        Some((previous_rsp, self.current.rsp))
+    }
+}
+
+// Saves the callee-saved registers and rsp of the running code to [rdi],
+// then restores the ones saved at rsi.
+global_asm!(r#"
+.intel_syntax noprefix
+.global context_switch
+context_switch:
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov [rdi], rsp
+    mov rsp, rsi
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    ret
+
+thread_trampoline:
+    mov rdi, r12
+    call thread_start
+    ud2
+.att_syntax
+"#);
+
+extern "C" {
+    fn context_switch(previous_rsp: *mut u64, next_rsp: u64);
+    fn thread_trampoline();
+}
+
+#[no_mangle]
+extern "C" fn thread_start(entry: fn()) -> ! {
+    // The switch to a new thread happens with interrupts disabled
+    x86_64::instructions::interrupts::enable();
+    entry();
+    exit()
+}
+
+/// Spawns a thread running `entry`
+///
+/// The thread is run once the running code yields or is preempted by the timer.
+pub fn spawn(entry: fn()) -> Result<ThreadId, MapToError<Size4KiB>> {
+    use crate::interrupts::critical_section;
+
+    let thread = Box::new(Thread::new(entry)?);
+    let id = thread.id;
+    let boot = Box::new(Thread::boot());
+
+    critical_section(|| {
+        SCHEDULER.lock()
+                 .get_or_insert_with(|| Scheduler {
+                     current: boot,
+                     ready: VecDeque::new(),
+                 })
+                 .ready
+                 .push_back(thread);
+    });
+
+    Ok(id)
+}
+
+/// Returns the id of the running thread, `None` if no thread was spawned yet
+pub fn current() -> Option<ThreadId> {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        SCHEDULER.lock()
+                 .as_ref()
+                 .map(|scheduler| scheduler.current.id)
+    })
+}
+
+/// Switches to the next ready thread, if any
+///
+/// Called by the timer interrupt handler, after the end of interrupt is signalled.
+pub fn schedule() {
+    switch(true);
+}
+
+/// Gives up the CPU to the next ready thread, if any
+pub fn yield_now() {
+    switch(true);
+}
+
+/// Finishes the running thread
+pub fn exit() -> ! {
+    switch(false);
+    unreachable!("A finished thread was resumed");
+}
+
+fn switch(requeue: bool) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let switch = SCHEDULER.try_lock()
+                              .and_then(|mut scheduler| {
+                                  scheduler.as_mut()?.switch_to_next(requeue)
+                              });
+
+        if let Some((previous_rsp, next_rsp)) = switch {
+            unsafe {
+                context_switch(previous_rsp, next_rsp);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+    use core::sync::atomic::AtomicUsize;
+
+    const INCREMENTS: usize = 100;
+
+    static FIRST: AtomicUsize = AtomicUsize::new(0);
+    static SECOND: AtomicUsize = AtomicUsize::new(0);
+
+    fn increment(counter: &AtomicUsize) {
+        for _ in 0..INCREMENTS {
+            counter.fetch_add(1, Ordering::Relaxed);
+            yield_now();
+        }
+    }
+
+    #[test_case]
+    fn clobbered_stack_canary_is_detected() {
+        let thread = Thread::new(|| {}).expect("Creating the thread failed");
+        assert!(thread.is_stack_intact());
+
+        let stack_bottom = thread.stack_top().unwrap() - (THREAD_STACK_PAGES * memory::PAGE_SIZE) as u64;
+        unsafe {
+            stack_bottom.as_mut_ptr::<u64>().write_volatile(0);
+        }
+
+        assert!(!thread.is_stack_intact());
+    }
+
+    #[test_case]
+    fn spawned_threads_both_advance() {
+        spawn(|| increment(&FIRST)).expect("Spawning the first thread failed");
+        spawn(|| increment(&SECOND)).expect("Spawning the second thread failed");
+
+        let deadline = time::uptime_ms() + 1000;
+        while (FIRST.load(Ordering::Relaxed) < INCREMENTS || SECOND.load(Ordering::Relaxed) < INCREMENTS)
+              && time::uptime_ms() < deadline {
+            yield_now();
+        }
+
+        assert_eq!(FIRST.load(Ordering::Relaxed), INCREMENTS);
+        assert_eq!(SECOND.load(Ordering::Relaxed), INCREMENTS);
+    }
+}
@@ -1,13 +1,121 @@
 use uart_16550::SerialPort;
 use spin::Mutex;
 use lazy_static::lazy_static;
+use x86_64::instructions::port::Port;
+use crate::port_io::{
+    MachinePorts,
+    PortIo,
+};
+
+/// The baud rate used if `init` is not called
+pub const DEFAULT_BAUD: u32 = 38400;
+
+/// The frequency of the UART clock divided by 16, the baud rate for a divisor of 1
+const UART_BASE_BAUD: u32 = 115_200;
+
+/// The offset of the divisor latch high byte register from the base port
+const DIVISOR_HIGH_OFFSET: u16 = 1;
+
+/// The offset of the line control register from the base port
+const LINE_CONTROL_OFFSET: u16 = 3;
+
+/// The offset of the line status register from the base port
+const LINE_STATUS_OFFSET: u16 = 5;
+
+/// The line control bit mapping the divisor latch to the first two registers
+const DIVISOR_LATCH_ACCESS: u8 = 0x80;
+
+/// The line control value for 8 data bits, no parity and one stop bit
+const EIGHT_N_ONE: u8 = 0x03;
+
+/// The line status bit set while a received byte is waiting in the data register
+const DATA_READY: u8 = 1;
+
+/// A standard serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl ComPort {
+    /// The base I/O port of the UART
+    pub fn base(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3f8,
+            ComPort::Com2 => 0x2f8,
+            ComPort::Com3 => 0x3e8,
+            ComPort::Com4 => 0x2e8,
+        }
+    }
+}
+
+/// The serial port the printing macros write to
+pub struct Serial {
+    port: SerialPort,
+    base: u16,
+}
+
+impl Serial {
+    fn new(com_port: ComPort) -> Self {
+        let base = com_port.base();
+        let mut port = unsafe { SerialPort::new(base) };
+        port.init();
+
+        Serial {
+            port,
+            base,
+        }
+    }
+}
 
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
-        Mutex::new(serial_port)
-    };
+    pub static ref SERIAL: Mutex<Serial> = Mutex::new(Serial::new(ComPort::Com1));
+}
+
+/// Makes the printing macros write to `com_port` at `baud` bits per second
+///
+/// Baud rates which cannot be represented by the UART's 16-bit divisor are clamped.
+/// Until this is called, output goes to COM1 at `DEFAULT_BAUD`.
+pub fn init(com_port: ComPort, baud: u32) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut serial = SERIAL.lock();
+
+        *serial = Serial::new(com_port);
+        set_divisor(&mut MachinePorts, serial.base, divisor(baud));
+    });
+}
+
+/// The divisor of the UART clock for the baud rate closest to `baud`
+fn divisor(baud: u32) -> u16 {
+    let divisor = UART_BASE_BAUD / baud.max(1);
+
+    divisor.max(1).min(u16::MAX as u32) as u16
+}
+
+/// Programs the divisor latch of the UART at `base`, keeping the 8N1 line format
+fn set_divisor(ports: &mut impl PortIo, base: u16, divisor: u16) {
+    let [low, high] = divisor.to_le_bytes();
+
+    ports.write_u8(base + LINE_CONTROL_OFFSET, DIVISOR_LATCH_ACCESS);
+    ports.write_u8(base, low);
+    ports.write_u8(base + DIVISOR_HIGH_OFFSET, high);
+    ports.write_u8(base + LINE_CONTROL_OFFSET, EIGHT_N_ONE);
+}
+
+/// Forcibly unlocks the serial interface.
+///
+/// ## Safety
+///
+/// The holder of the lock may be in the middle of writing. This is only sound
+/// when it never runs again, like in a panic handler on a single core with
+/// interrupts disabled, right before halting.
+pub unsafe fn force_unlock() {
+    SERIAL.force_unlock();
 }
 
 /// Prints to the host system through the serial interface
@@ -30,11 +138,154 @@ macro_rules! serial_println {
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
+    use crate::interrupts::critical_section;
 
-    interrupts::without_interrupts(|| {
-        SERIAL1.lock()
-               .write_fmt(args)
-               .expect("Printing to serial failed");
+    critical_section(|| {
+        SERIAL.lock()
+              .port
+              .write_fmt(args)
+              .expect("Printing to serial failed");
     });
-}
\ No newline at end of file
+}
+
+/// Prints to serial unless the serial interface is locked, returning whether it printed
+///
+/// For the handlers of exceptions which can interrupt even a print with interrupts disabled.
+pub fn try_print(args: ::core::fmt::Arguments) -> bool {
+    use core::fmt::Write;
+
+    match SERIAL.try_lock() {
+        Some(mut serial) => serial.port.write_fmt(args).is_ok(),
+        None             => false,
+    }
+}
+
+/// Prints the source location, the text and the `Debug` value of an expression
+/// to serial and returns its value, like `std::dbg!`
+///
+/// The value is moved in and back out, so it can wrap any expression inline.
+/// Several expressions are returned as a tuple.
+#[macro_export]
+macro_rules! dbg {
+    () => {
+        $crate::serial_println!("[{}:{}]", file!(), line!())
+    };
+    ($val:expr $(,)?) => {
+        // `match` keeps the temporaries of the expression alive, like `std::dbg!`
+        match $val {
+            tmp => {
+                $crate::serial::_dbg(file!(), line!(), stringify!($val), &tmp);
+                tmp
+            }
+        }
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($crate::dbg!($val)),+,)
+    };
+}
+
+/// The line printed by `dbg!`, like `[src/main.rs:10] 2 + 2 = 4`
+struct DbgLine<'a> {
+    file: &'a str,
+    line: u32,
+    expr: &'a str,
+    value: &'a dyn core::fmt::Debug,
+}
+
+impl core::fmt::Display for DbgLine<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "[{}:{}] {} = {:#?}", self.file, self.line, self.expr, self.value)
+    }
+}
+
+/// The last line printed by `dbg!`, so that tests can check it
+#[cfg(test)]
+static LAST_DBG_LINE: Mutex<crate::util::FixedString<256>> = Mutex::new(crate::util::FixedString::new());
+
+#[doc(hidden)]
+pub fn _dbg(file: &str, line: u32, expr: &str, value: &dyn core::fmt::Debug) {
+    let dbg_line = DbgLine { file, line, expr, value };
+
+    #[cfg(test)]
+    {
+        use core::fmt::Write;
+
+        let mut last = LAST_DBG_LINE.lock();
+        last.clear();
+        let _ = write!(last, "{}", dbg_line);
+    }
+
+    _print(format_args!("{}\n", dbg_line));
+}
+
+/// Reads a byte received through the serial interface, if there is one
+pub fn serial_try_read() -> Option<u8> {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let serial = SERIAL.lock();
+        let mut line_status: Port<u8> = Port::new(serial.base + LINE_STATUS_OFFSET);
+        let mut data: Port<u8> = Port::new(serial.base);
+
+        unsafe {
+            if line_status.read() & DATA_READY != 0 {
+                Some(data.read())
+            } else {
+                None
+            }
+        }
+    })
+}
+
+/// Waits for a byte to be received through the serial interface and returns it
+pub fn serial_read_byte() -> u8 {
+    loop {
+        if let Some(byte) = serial_try_read() {
+            return byte;
+        }
+        core::sync::atomic::spin_loop_hint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        port_io::RecordingPorts,
+        time,
+    };
+
+    #[test_case]
+    fn serial_try_read_returns_promptly_without_input() {
+        let start = time::ticks();
+
+        assert_eq!(serial_try_read(), None);
+        assert!(time::ticks() - start <= 1);
+    }
+
+    #[test_case]
+    fn baud_rate_is_programmed_through_the_divisor_latch() {
+        let mut ports = RecordingPorts::new();
+
+        set_divisor(&mut ports, ComPort::Com2.base(), divisor(9600));
+
+        assert_eq!(ports.writes, [(0x2fb, 0x80), (0x2f8, 12), (0x2f9, 0), (0x2fb, 0x03)]);
+    }
+
+    #[test_case]
+    fn dbg_prints_the_expression_and_returns_its_value() {
+        let value = crate::dbg!(2 + 2);
+
+        assert_eq!(value, 4);
+        let last = LAST_DBG_LINE.lock();
+        assert!(last.as_str().starts_with("[src/serial.rs:"));
+        assert!(last.as_str().ends_with("] 2 + 2 = 4"));
+    }
+
+    #[test_case]
+    fn unrepresentable_baud_rates_are_clamped() {
+        assert_eq!(divisor(DEFAULT_BAUD), 3);
+        assert_eq!(divisor(0), u16::MAX);
+        assert_eq!(divisor(1_000_000), 1);
+    }
+}
@@ -0,0 +1,180 @@
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use crate::{
+    print,
+    serial_print,
+    input,
+    memory,
+    power,
+    time,
+    vga_buffer,
+};
+
+/// Prints to the screen and to serial, so that the shell can also be used headless
+macro_rules! shell_println {
+    ($($arg:tt)*) => ({
+        $crate::println!($($arg)*);
+        $crate::serial_println!($($arg)*);
+    });
+}
+
+const PROMPT: &str = "> ";
+
+/// The longest command line read by the shell, in bytes
+const MAX_LINE_LENGTH: usize = 256;
+
+/// The most arguments passed to a command handler, the rest of them are dropped
+const MAX_ARGS: usize = 16;
+
+/// A function called with the arguments of the command it is registered for
+pub type CommandHandler = fn(&[&str]);
+
+#[derive(Clone, Copy)]
+struct Command {
+    name: &'static str,
+    handler: CommandHandler,
+}
+
+const BUILTIN_COMMANDS: [(&str, CommandHandler); 5] = [
+    ("help", help),
+    ("mem", mem),
+    ("clear", clear),
+    ("uptime", uptime),
+    ("reboot", reboot),
+];
+
+lazy_static! {
+    static ref COMMANDS: spin::Mutex<Vec<Command>> = spin::Mutex::new(
+        BUILTIN_COMMANDS
+        .iter()
+        .map(|&(name, handler)| Command { name, handler })
+        .collect()
+    );
+}
+
+/// Registers `handler` for the command `name`, replacing the handler registered for it before
+pub fn register(name: &'static str, handler: CommandHandler) {
+    let mut commands = COMMANDS.lock();
+
+    match commands.iter_mut().find(|command| command.name == name) {
+        Some(command) => command.handler = handler,
+        None          => commands.push(Command { name, handler }),
+    }
+}
+
+/// Reads and runs commands forever
+pub fn run() -> ! {
+    loop {
+        run_once();
+    }
+}
+
+/// Prints the prompt, then reads a line from the keyboard or serial and runs the command on it
+pub fn run_once() {
+    print!("{}", PROMPT);
+    serial_print!("{}", PROMPT);
+
+    let mut line = [0; MAX_LINE_LENGTH];
+    let len = input::read_line(&mut line);
+
+    // read_line stores whole UTF-8 encoded characters only
+    execute(core::str::from_utf8(&line[..len]).unwrap_or(""));
+}
+
+/// Runs the command named by the first word of `line` with the rest of the words as arguments
+///
+/// Words are separated by whitespace and empty lines are ignored.
+/// Returns false if there is no such command.
+pub fn execute(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None       => return true,
+    };
+
+    let mut args = [""; MAX_ARGS];
+    let count = args.iter_mut()
+                    .zip(words)
+                    .map(|(arg, word)| *arg = word)
+                    .count();
+
+    // The table is unlocked before running the handler, so that it can register commands
+    let handler = COMMANDS.lock()
+                          .iter()
+                          .find(|command| command.name == name)
+                          .map(|command| command.handler);
+
+    match handler {
+        Some(handler) => {
+            handler(&args[..count]);
+            true
+        },
+        None => {
+            shell_println!("unknown command: {}", name);
+            false
+        },
+    }
+}
+
+fn help(_args: &[&str]) {
+    shell_println!("commands:");
+    for command in COMMANDS.lock().iter() {
+        shell_println!("  {}", command.name);
+    }
+}
+
+fn mem(_args: &[&str]) {
+    shell_println!("{}", memory::stats());
+}
+
+fn clear(_args: &[&str]) {
+    vga_buffer::clear_screen();
+}
+
+fn uptime(_args: &[&str]) {
+    let ms = time::uptime_ms();
+
+    shell_println!("{}.{:03} seconds since boot", ms / 1000, ms % 1000);
+}
+
+fn reboot(_args: &[&str]) {
+    power::reboot();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard;
+    use core::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    #[test_case]
+    fn typed_uptime_command_prints_the_uptime() {
+        // u, p, t, i, m, e, Enter
+        let scancodes = [0x16, 0x96, 0x19, 0x99, 0x14, 0x94, 0x17, 0x97, 0x32, 0xb2, 0x12, 0x92, 0x1c, 0x9c];
+        for &scancode in scancodes.iter() {
+            keyboard::add_scancode(scancode);
+        }
+
+        run_once();
+
+        assert!(vga_buffer::screen_contains("seconds since boot"));
+    }
+
+    #[test_case]
+    fn registered_commands_are_run_with_their_arguments() {
+        static ARGS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        fn count_args(args: &[&str]) {
+            ARGS_COUNT.store(args.len(), Ordering::Relaxed);
+        }
+
+        register("count-args", count_args);
+
+        assert!(execute("  count-args one  two three "));
+        assert_eq!(ARGS_COUNT.load(Ordering::Relaxed), 3);
+        assert!(!execute("no-such-command"));
+    }
+}
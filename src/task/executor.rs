@@ -0,0 +1,160 @@
+use super::{
+    Task,
+    TaskId,
+};
+use alloc::{
+    collections::BTreeMap,
+    sync::Arc,
+    task::Wake,
+};
+use core::task::{
+    Context,
+    Poll,
+    Waker,
+};
+use crossbeam_queue::ArrayQueue;
+use crate::time;
+
+/// The maximum number of tasks which can be ready to run at once
+const TASK_QUEUE_CAPACITY: usize = 100;
+
+/// Runs tasks cooperatively, polling only those which were woken
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+    waker_cache: BTreeMap<TaskId, Waker>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            tasks: BTreeMap::new(),
+            task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
+            waker_cache: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a task to the executor and marks it as ready to run.
+    ///
+    /// Panics if a task with the same id was spawned already or the task queue is full.
+    pub fn spawn(&mut self, task: Task) {
+        let task_id = task.id;
+
+        if self.tasks.insert(task_id, task).is_some() {
+            panic!("Task with the same id was spawned already");
+        }
+        self.task_queue
+            .push(task_id)
+            .expect("Task queue is full");
+    }
+
+    /// Runs the spawned tasks forever.
+    ///
+    /// The CPU is halted while no task is ready to run.
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_ready_tasks();
+            time::run_due_timeouts();
+            self.sleep_if_idle();
+        }
+    }
+
+    fn run_ready_tasks(&mut self) {
+        let Self {
+            tasks,
+            task_queue,
+            waker_cache,
+        } = self;
+
+        while let Ok(task_id) = task_queue.pop() {
+            let task = match tasks.get_mut(&task_id) {
+                Some(task) => task,
+                None       => continue, // the task has completed already
+            };
+            let waker = waker_cache
+                .entry(task_id)
+                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
+            let mut context = Context::from_waker(waker);
+
+            if let Poll::Ready(()) = task.poll(&mut context) {
+                tasks.remove(&task_id);
+                waker_cache.remove(&task_id);
+            }
+        }
+    }
+
+    /// Halts until the next interrupt if no task is ready to run.
+    ///
+    /// Interrupts are disabled while checking the task queue, so a wake up
+    /// from an interrupt handler cannot be missed.
+    fn sleep_if_idle(&self) {
+        use x86_64::instructions::interrupts;
+
+        interrupts::disable();
+        if self.task_queue.is_empty() {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Wakes a task by pushing its id to the task queue of the executor
+struct TaskWaker {
+    task_id: TaskId,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+}
+
+impl TaskWaker {
+    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            task_id,
+            task_queue,
+        }))
+    }
+
+    fn wake_task(&self) {
+        self.task_queue
+            .push(self.task_id)
+            .expect("Task queue is full");
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    };
+
+    static FIRST_RAN: AtomicBool = AtomicBool::new(false);
+    static SECOND_RAN: AtomicBool = AtomicBool::new(false);
+
+    async fn set_flag(flag: &'static AtomicBool) {
+        flag.store(true, Ordering::SeqCst);
+    }
+
+    #[test_case]
+    fn spawned_tasks_are_run() {
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(set_flag(&FIRST_RAN)));
+        executor.spawn(Task::new(set_flag(&SECOND_RAN)));
+
+        executor.run_ready_tasks();
+
+        assert!(FIRST_RAN.load(Ordering::SeqCst));
+        assert!(SECOND_RAN.load(Ordering::SeqCst));
+        assert!(executor.tasks.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use core::sync::atomic::{
+    AtomicU32,
+    AtomicU64,
+    Ordering,
+};
+use crate::interrupts::pit;
+
+/// The number of timer interrupts since boot
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// The most timeouts which can be pending at once
+const MAX_TIMEOUTS: usize = 32;
+
+/// Marks that no timeout is pending
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// The earliest tick a pending timeout fires at, so that the timer interrupt
+/// only looks through the timeouts when one of them is due
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(NO_DEADLINE);
+
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a timeout set with `set_timeout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u64);
+
+#[derive(Clone, Copy)]
+struct Timeout {
+    id: TimerId,
+    fire_tick: u64,
+    callback: fn(),
+    /// Set by the timer interrupt once the fire tick is reached
+    due: bool,
+}
+
+/// The pending timeouts. The timer interrupt handler locks it,
+/// so it must only be locked with interrupts disabled otherwise.
+static TIMEOUTS: spin::Mutex<[Option<Timeout>; MAX_TIMEOUTS]> = spin::Mutex::new([None; MAX_TIMEOUTS]);
+
+/// The divisor the PIT is currently programmed with.
+/// Until it is reprogrammed, the PIT runs with the BIOS default of 65536.
+static TIMER_DIVISOR: AtomicU32 = AtomicU32::new(65536);
+
+/// Records a timer interrupt, marking the timeouts which fire at it as due
+pub(crate) fn tick() {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    if now >= NEXT_DEADLINE.load(Ordering::Relaxed) {
+        let mut timeouts = TIMEOUTS.lock();
+        let mut next_deadline = NO_DEADLINE;
+
+        for timeout in timeouts.iter_mut().flatten().filter(|timeout| !timeout.due) {
+            if timeout.fire_tick <= now {
+                timeout.due = true;
+            } else {
+                next_deadline = next_deadline.min(timeout.fire_tick);
+            }
+        }
+
+        NEXT_DEADLINE.store(next_deadline, Ordering::Relaxed);
+    }
+}
+
+/// Records the divisor the PIT was programmed with
+pub(crate) fn set_timer_divisor(divisor: u16) {
+    TIMER_DIVISOR.store(u32::from(divisor), Ordering::Relaxed);
+}
+
+/// Returns the number of timer interrupts since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the time elapsed since boot in milliseconds
+pub fn uptime_ms() -> u64 {
+    ticks_to_ms(ticks())
+}
+
+/// Converts a number of timer ticks to milliseconds at the current timer frequency
+pub fn ticks_to_ms(ticks: u64) -> u64 {
+    let divisor = u128::from(TIMER_DIVISOR.load(Ordering::Relaxed));
+    let ms = u128::from(ticks) * divisor * 1000 / u128::from(pit::BASE_FREQUENCY);
+
+    ms as u64
+}
+
+/// Converts a duration in milliseconds to the number of timer ticks
+/// it spans at the current timer frequency, rounding up
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    let divisor = u128::from(TIMER_DIVISOR.load(Ordering::Relaxed));
+    let scaled_ms = u128::from(ms) * u128::from(pit::BASE_FREQUENCY);
+    let ticks = (scaled_ms + divisor * 1000 - 1) / (divisor * 1000);
+
+    ticks as u64
+}
+
+/// Blocks for at least `ms` milliseconds
+///
+/// The CPU is halted between timer interrupts, so interrupts are enabled
+/// while sleeping. Their previous state is restored before returning.
+pub fn sleep_ms(ms: u64) {
+    use x86_64::instructions::interrupts;
+
+    if ms == 0 {
+        return;
+    }
+
+    let were_enabled = interrupts::are_enabled();
+    let ticks_to_wait = ms_to_ticks(ms);
+    let start = ticks();
+
+    while ticks() - start < ticks_to_wait {
+        interrupts::enable_and_hlt();
+    }
+
+    if !were_enabled {
+        interrupts::disable();
+    }
+}
+
+/// Calls `callback` once at least `after_ms` milliseconds have passed
+///
+/// The timer interrupt only marks the timeout as due, the callback is called
+/// by the next `run_due_timeouts`, outside of interrupt context.
+/// Returns None if `MAX_TIMEOUTS` timeouts are already pending.
+pub fn set_timeout(after_ms: u64, callback: fn()) -> Option<TimerId> {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut timeouts = TIMEOUTS.lock();
+        let slot = timeouts.iter_mut().find(|slot| slot.is_none())?;
+
+        let id = TimerId(NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed));
+        let fire_tick = ticks() + ms_to_ticks(after_ms);
+        *slot = Some(Timeout {
+            id,
+            fire_tick,
+            callback,
+            due: false,
+        });
+        NEXT_DEADLINE.fetch_min(fire_tick, Ordering::Relaxed);
+
+        Some(id)
+    })
+}
+
+/// Cancels a timeout whose callback has not been called yet, returning whether it was pending
+pub fn cancel_timeout(id: TimerId) -> bool {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut timeouts = TIMEOUTS.lock();
+
+        match timeouts.iter_mut().find(|slot| matches!(slot, Some(timeout) if timeout.id == id)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            },
+            None => false,
+        }
+    })
+}
+
+/// Calls the callbacks of the due timeouts
+///
+/// The idle loops call this after each interrupt. The callbacks run with
+/// the timeouts unlocked, so they can set further ones.
+pub fn run_due_timeouts() {
+    use crate::interrupts::critical_section;
+
+    let take_due = || critical_section(|| {
+        TIMEOUTS.lock()
+                .iter_mut()
+                .find(|slot| matches!(slot, Some(timeout) if timeout.due))
+                .and_then(Option::take)
+    });
+
+    while let Some(timeout) = take_due() {
+        (timeout.callback)();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn sleep_ms_waits_for_roughly_the_given_time() {
+        let start = uptime_ms();
+        sleep_ms(50);
+        let elapsed = uptime_ms() - start;
+
+        assert!(elapsed >= 50);
+        assert!(elapsed < 150);
+    }
+
+    #[test_case]
+    fn timeout_fires_roughly_on_time() {
+        static FIRED_AT: AtomicU64 = AtomicU64::new(NO_DEADLINE);
+
+        fn record_uptime() {
+            FIRED_AT.store(uptime_ms(), Ordering::Relaxed);
+        }
+
+        let start = uptime_ms();
+        assert!(set_timeout(50, record_uptime).is_some());
+
+        while FIRED_AT.load(Ordering::Relaxed) == NO_DEADLINE && uptime_ms() - start < 500 {
+            x86_64::instructions::hlt();
+            run_due_timeouts();
+        }
+
+        let elapsed = FIRED_AT.load(Ordering::Relaxed) - start;
+        assert!(elapsed >= 50);
+        assert!(elapsed < 150);
+    }
+
+    #[test_case]
+    fn cancelled_timeout_does_not_fire() {
+        static FIRED: AtomicU64 = AtomicU64::new(0);
+
+        fn count_firing() {
+            FIRED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let id = set_timeout(10, count_firing).expect("Too many pending timeouts");
+        assert!(cancel_timeout(id));
+
+        sleep_ms(30);
+        run_due_timeouts();
+
+        assert_eq!(FIRED.load(Ordering::Relaxed), 0);
+        assert!(!cancel_timeout(id));
+    }
+
+    #[test_case]
+    fn sleep_ms_with_zero_returns_immediately() {
+        let start = ticks();
+        sleep_ms(0);
+
+        assert!(ticks() - start <= 1);
+    }
+}
@@ -0,0 +1,114 @@
+use core::fmt;
+
+/// A string of at most `N` bytes which needs no heap
+///
+/// Formatted text can be written to it before the Heap is initialised
+/// and printed once complete. Text which does not fit is cut off
+/// at a character boundary.
+pub struct FixedString<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FixedString<N> {
+    pub const fn new() -> Self {
+        FixedString {
+            bytes: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole characters of `str`s are copied in
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Checks if some of the written text did not fit
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+}
+
+impl<const N: usize> Default for FixedString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for FixedString<N> {
+    /// Appends as much of `s` as fits, never failing
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut end = s.len().min(N - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        self.truncated |= end < s.len();
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test_case]
+    fn formatted_text_is_appended() {
+        let mut s = FixedString::<32>::new();
+
+        write!(s, "{} + {}", 1, 2).unwrap();
+        s.write_str(" = 3").unwrap();
+
+        assert_eq!(s.as_str(), "1 + 2 = 3");
+        assert!(!s.is_truncated());
+    }
+
+    #[test_case]
+    fn text_past_the_capacity_is_cut_off_at_a_character_boundary() {
+        let mut s = FixedString::<5>::new();
+
+        write!(s, "abcé").unwrap();
+        s.write_str("d").unwrap();
+        assert_eq!(s.as_str(), "abcé");
+
+        s.clear();
+        write!(s, "abcdé").unwrap();
+        assert_eq!(s.as_str(), "abcd");
+        assert!(s.is_truncated());
+    }
+
+    #[test_case]
+    fn fixed_string_is_displayed_as_its_text() {
+        use alloc::format;
+
+        let mut s = FixedString::<16>::new();
+        write!(s, "early {}", "boot").unwrap();
+
+        assert_eq!(format!("[{}]", s), "[early boot]");
+    }
+}
@@ -0,0 +1,7 @@
+mod fixed_string;
+mod once;
+mod ring_buffer;
+
+pub use fixed_string::FixedString;
+pub use once::Once;
+pub use ring_buffer::RingBuffer;
@@ -0,0 +1,170 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{
+        AtomicU8,
+        Ordering,
+    },
+};
+
+const UNINITIALIZED: u8 = 0;
+const INITIALIZING: u8 = 1;
+const INITIALIZED: u8 = 2;
+
+/// A value which is set once and read many times, like a global set up during boot
+///
+/// Unlike `lazy_static!`, the value need not be known where it is declared.
+/// It is initialised with interrupts disabled, so interrupt handlers reading it
+/// never wait for the context they interrupted.
+pub struct Once<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+
+impl<T> Once<T> {
+    pub const fn new() -> Self {
+        Once {
+            state: AtomicU8::new(UNINITIALIZED),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Returns the value if it is initialised
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == INITIALIZED {
+            Some(unsafe { &*(*self.value.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value, panicking with `msg` if it is not initialised yet
+    pub fn expect(&self, msg: &str) -> &T {
+        match self.get() {
+            Some(value) => value,
+            None        => panic!("{}", msg),
+        }
+    }
+
+    /// Initialises the value, handing it back if it was initialised before
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+
+        match value {
+            Some(value) => Err(value),
+            None        => Ok(()),
+        }
+    }
+
+    /// Returns the value, initialising it with `f` if it is not initialised yet
+    ///
+    /// Initialisation runs with interrupts disabled on a single core, so the value is
+    /// only ever seen being initialised by `f` itself or, once `f` panicked, by the panic
+    /// handler. Neither could wait for the initialisation to finish, so this panics instead.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        use crate::interrupts::critical_section;
+
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        critical_section(|| {
+            match self.state.compare_and_swap(UNINITIALIZED, INITIALIZING, Ordering::Acquire) {
+                UNINITIALIZED => {
+                    let value = f();
+                    unsafe {
+                        (*self.value.get()).as_mut_ptr().write(value);
+                    }
+                    self.state.store(INITIALIZED, Ordering::Release);
+                },
+                INITIALIZING => panic!("Once used while it is being initialised"),
+                _            => {},
+            }
+        });
+
+        self.get().unwrap()
+    }
+}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == INITIALIZED {
+            unsafe {
+                (*self.value.get()).as_mut_ptr().drop_in_place();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+
+    #[test_case]
+    fn value_is_initialised_only_once() {
+        let once = Once::new();
+        assert_eq!(once.get(), None);
+
+        assert_eq!(once.set(1), Ok(()));
+        assert_eq!(once.set(2), Err(2));
+        assert_eq!(*once.get_or_init(|| 3), 1);
+        assert_eq!(once.get(), Some(&1));
+    }
+
+    #[test_case]
+    fn get_or_init_calls_the_initialiser_once() {
+        static ONCE: Once<usize> = Once::new();
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let init = || CALLS.fetch_add(1, Ordering::Relaxed) + 41;
+        let values: [usize; 4] = [
+            *ONCE.get_or_init(init),
+            *ONCE.get_or_init(init),
+            *ONCE.get_or_init(|| 0),
+            *ONCE.expect("Once used before initialisation"),
+        ];
+
+        assert_eq!(values, [41; 4]);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test_case]
+    fn value_being_initialised_is_not_seen() {
+        use x86_64::instructions::interrupts;
+
+        static ONCE: Once<[u64; 4]> = Once::new();
+
+        // Like an interrupt handler would if it could run meanwhile
+        let value = ONCE.get_or_init(|| {
+            assert!(ONCE.get().is_none());
+            assert!(!interrupts::are_enabled());
+            [7; 4]
+        });
+
+        assert_eq!(*value, [7; 4]);
+        assert!(interrupts::are_enabled());
+    }
+
+    #[test_case]
+    fn initialised_value_is_dropped_with_the_once() {
+        use alloc::rc::Rc;
+
+        let value = Rc::new(());
+        let once = Once::new();
+        assert!(once.set(value.clone()).is_ok());
+        assert_eq!(Rc::strong_count(&value), 2);
+
+        drop(once);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,162 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// A bounded FIFO queue of at most `N` items which needs no heap
+///
+/// It is a single-producer single-consumer queue: it may be pushed to from one context,
+/// like an interrupt handler, while it is popped from another, like the main loop.
+/// Pushing from two contexts which can interrupt each other, or popping from two,
+/// loses or duplicates items, so callers must prevent it, e.g. by disabling interrupts.
+pub struct RingBuffer<T, const N: usize> {
+    slots: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// The number of items ever popped, only advanced by the consumer
+    head: AtomicUsize,
+    /// The number of items ever pushed, only advanced by the producer
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    const EMPTY_SLOT: MaybeUninit<T> = MaybeUninit::uninit();
+
+    pub const fn new() -> Self {
+        RingBuffer {
+            slots: UnsafeCell::new([Self::EMPTY_SLOT; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends an item, handing it back if the buffer is full
+    ///
+    /// Only the producer may call it.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+
+        if tail.wrapping_sub(head) == N {
+            return Err(item);
+        }
+
+        unsafe {
+            self.slot(tail).write(MaybeUninit::new(item));
+        }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Removes the oldest item, if there is one
+    ///
+    /// Only the consumer may call it.
+    pub fn try_pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        let item = unsafe { self.slot(head).read().assume_init() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+
+        Some(item)
+    }
+
+    /// Returns the number of items in the buffer, which may change right away
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+
+        tail.wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// The slot of the item with the given count
+    ///
+    /// Only the slots of different counts are accessed at the same time,
+    /// so no reference to the whole array is made.
+    fn slot(&self, count: usize) -> *mut MaybeUninit<T> {
+        unsafe { (self.slots.get() as *mut MaybeUninit<T>).add(count % N) }
+    }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn items_are_popped_in_order_across_the_wraparound() {
+        let buffer: RingBuffer<u32, 4> = RingBuffer::new();
+
+        for i in 0..10 {
+            assert_eq!(buffer.try_push(2 * i), Ok(()));
+            assert_eq!(buffer.try_push(2 * i + 1), Ok(()));
+            assert_eq!(buffer.try_pop(), Some(2 * i));
+            assert_eq!(buffer.try_pop(), Some(2 * i + 1));
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test_case]
+    fn full_buffer_hands_back_the_item() {
+        let buffer: RingBuffer<u32, 3> = RingBuffer::new();
+        for i in 0..3 {
+            assert_eq!(buffer.try_push(i), Ok(()));
+        }
+
+        assert_eq!(buffer.try_push(3), Err(3));
+        assert_eq!(buffer.len(), buffer.capacity());
+        assert_eq!(buffer.try_pop(), Some(0));
+        assert_eq!(buffer.try_push(3), Ok(()));
+    }
+
+    #[test_case]
+    fn empty_buffer_pops_nothing() {
+        let buffer: RingBuffer<u32, 2> = RingBuffer::new();
+        assert_eq!(buffer.try_pop(), None);
+
+        assert_eq!(buffer.try_push(1), Ok(()));
+        assert_eq!(buffer.try_pop(), Some(1));
+        assert_eq!(buffer.try_pop(), None);
+    }
+
+    #[test_case]
+    fn remaining_items_are_dropped_with_the_buffer() {
+        use alloc::rc::Rc;
+
+        let item = Rc::new(());
+        let buffer: RingBuffer<Rc<()>, 2> = RingBuffer::new();
+        assert!(buffer.try_push(item.clone()).is_ok());
+        assert_eq!(Rc::strong_count(&item), 2);
+
+        drop(buffer);
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+}
@@ -1,25 +1,43 @@
 use core::fmt;
 use volatile::Volatile;
+use x86_64::VirtAddr;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(
-        Writer::new(
-            ColorCode::new(
-                Color::Cyan,
-                Color::Black
-            )
-        )
-    );
+    pub static ref WRITER: Mutex<Writer> = Mutex::new({
+        let theme = theme();
+
+        Writer::new(ColorCode::new(theme.default_fg, theme.default_bg))
+    });
 }
 
-const BUFFER_WIDTH: usize = 80;
-const BUFFER_HEIGHT: usize = 25;
+/// The physical address of the VGA text buffer
+const BUFFER_ADDRESS: u64 = 0xb8000;
+
+/// The dimensions of the standard 80x25 text mode, used when the BIOS reports none
+const DEFAULT_WIDTH: usize = 80;
+const DEFAULT_HEIGHT: usize = 25;
+
+/// The dimensions of the largest text mode supported
+const MAX_WIDTH: usize = 132;
+const MAX_HEIGHT: usize = 60;
+
+/// The BIOS data area fields with the number of columns (a word)
+/// and the number of rows minus one (a byte) of the current text mode
+const BDA_COLUMNS_ADDRESS: u64 = 0x44a;
+const BDA_LAST_ROW_ADDRESS: u64 = 0x484;
 
+/// The distance between two tab stops in columns
+const TAB_WIDTH: usize = 8;
+
+/// The number of rows scrolled off the screen which are kept to be scrolled back to
+const SCROLLBACK_ROWS: usize = 100;
+
+/// The VGA text buffer, with rows of the writer's width laid out one after the other
 #[repr(transparent)]
 struct Buffer {
-    chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    chars: [Volatile<ScreenChar>; MAX_WIDTH * MAX_HEIGHT],
 }
 
 /// A screen character in the VGA text buffer
@@ -41,6 +59,14 @@ impl ColorCode {
             (background as u8) << 4 | (foreground as u8) 
         )
     }
+
+    fn foreground(self) -> Color {
+        Color::ALL[usize::from(self.0 & 0x0f)]
+    }
+
+    fn background(self) -> Color {
+        Color::ALL[usize::from(self.0 >> 4)]
+    }
 }
 
 /// Represents the standard color palette in VGA text mode
@@ -66,29 +92,425 @@ pub enum Color {
     White = 15,
 }
 
+impl Color {
+    /// The colors of the palette indexed by their code
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    /// Returns the color with the given code, if there is one
+    pub fn from_u8(code: u8) -> Option<Color> {
+        Color::ALL.get(usize::from(code)).copied()
+    }
+}
+
+/// The colors of the text printed to the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// The colors of text printed without a color of its own
+    pub default_fg: Color,
+    pub default_bg: Color,
+    /// The colors of the log messages of the `Error`, `Warn` and `Info` levels
+    pub error_fg: Color,
+    pub warn_fg: Color,
+    pub info_fg: Color,
+}
+
+impl Theme {
+    /// The theme used unless another one is set
+    pub const fn dark() -> Theme {
+        Theme {
+            default_fg: Color::Cyan,
+            default_bg: Color::Black,
+            error_fg: Color::Red,
+            warn_fg: Color::Yellow,
+            info_fg: Color::White,
+        }
+    }
+
+    /// A theme of bright colors only, for screens which are hard to read
+    pub const fn high_contrast() -> Theme {
+        Theme {
+            default_fg: Color::White,
+            default_bg: Color::Black,
+            error_fg: Color::LightRed,
+            warn_fg: Color::Yellow,
+            info_fg: Color::LightCyan,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+static THEME: Mutex<Theme> = Mutex::new(Theme::dark());
+
+/// Returns the theme set last
+pub fn theme() -> Theme {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| *THEME.lock())
+}
+
+/// Makes the writer use the default colors of the theme from now on,
+/// including after an ANSI reset, and the log messages use its level colors
+///
+/// The text already on the screen keeps its colors.
+pub fn set_theme(theme: Theme) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        *THEME.lock() = theme;
+
+        let mut writer = WRITER.lock();
+        let color_code = ColorCode::new(theme.default_fg, theme.default_bg);
+
+        writer.color_code = color_code;
+        writer.default_color_code = color_code;
+    });
+}
+
+/// The colors selected by the ANSI SGR codes 30-37 and 40-47, in code order
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+/// The maximum number of parameters kept for a CSI sequence, the rest are ignored
+const MAX_CSI_PARAMS: usize = 4;
+
+/// The numeric parameters of a CSI sequence, separated by ';'
+#[derive(Debug, Clone, Copy)]
+struct CsiParams {
+    values: [u16; MAX_CSI_PARAMS],
+    current: usize,
+}
+
+impl CsiParams {
+    fn new() -> Self {
+        CsiParams {
+            values: [0; MAX_CSI_PARAMS],
+            current: 0,
+        }
+    }
+
+    fn push_digit(&mut self, digit: u8) {
+        if let Some(value) = self.values.get_mut(self.current) {
+            *value = value.saturating_mul(10)
+                          .saturating_add(u16::from(digit));
+        }
+    }
+
+    fn next_param(&mut self) {
+        self.current = (self.current + 1).min(MAX_CSI_PARAMS);
+    }
+
+    /// The parameters read so far. Omitted parameters are 0.
+    fn values(&self) -> &[u16] {
+        &self.values[..(self.current + 1).min(MAX_CSI_PARAMS)]
+    }
+
+    /// The parameter at `index` or `default` if it is omitted or 0
+    fn get_or(&self, index: usize, default: u16) -> u16 {
+        match self.values().get(index) {
+            Some(&value) if value != 0 => value,
+            _                          => default,
+        }
+    }
+}
+
+/// The state of the ANSI escape sequence parser
+#[derive(Debug, Clone, Copy)]
+enum EscapeState {
+    Ground,
+    Escape,
+    Csi(CsiParams),
+}
+
 /// A writer type that allows writing ASCII bytes and strings to an underlying `Buffer`.
 pub struct Writer {
+    row_position: usize,
     column_position: usize,
     color_code: ColorCode,
+    default_color_code: ColorCode,
+    escape_state: EscapeState,
+    width: usize,
+    height: usize,
+    scroll_enabled: bool,
+    word_wrap: bool,
+    /// The column at which the word being written on the current row starts, in word-wrap mode
+    word_start: Option<usize>,
+    /// The cells being edited, laid out like the ones of `buffer`
+    backing: [ScreenChar; MAX_WIDTH * MAX_HEIGHT],
+    /// The range of cells edited since the last flush
+    dirty: Option<(usize, usize)>,
+    /// The rows scrolled off the screen, each taking `MAX_WIDTH` cells, in a ring starting at `history_start`
+    history: [ScreenChar; SCROLLBACK_ROWS * MAX_WIDTH],
+    history_start: usize,
+    history_len: usize,
+    /// The number of history rows shown above the current screen
+    view_offset: usize,
     buffer: &'static mut Buffer,
 }
 
 impl Writer {
     /// Creates a new Writer which writes to the VGA text buffer
     fn new(color_code: ColorCode) -> Self {
+        let buffer = unsafe { &mut *(BUFFER_ADDRESS as *mut Buffer) };
+
+        Self::with_buffer(color_code, buffer)
+    }
+
+    fn with_buffer(color_code: ColorCode, buffer: &'static mut Buffer) -> Self {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code,
+        };
+
         Writer {
             color_code,
+            default_color_code: color_code,
+            escape_state: EscapeState::Ground,
+            row_position: DEFAULT_HEIGHT - 1,
             column_position: 0,
-            buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            scroll_enabled: true,
+            word_wrap: false,
+            word_start: None,
+            backing: [blank; MAX_WIDTH * MAX_HEIGHT],
+            dirty: None,
+            history: [blank; SCROLLBACK_ROWS * MAX_WIDTH],
+            history_start: 0,
+            history_len: 0,
+            view_offset: 0,
+            buffer,
+        }
+    }
+
+    /// Copies the cells edited since the last flush to the VGA text buffer
+    ///
+    /// The printing macros flush after each call. Users of `WRITER` must flush
+    /// for their edits to show up on the screen.
+    pub fn flush(&mut self) {
+        let (start, end) = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None        => return,
+        };
+
+        if self.view_offset == 0 {
+            write_cells(&mut self.buffer.chars[start..end], &self.backing[start..end]);
+        } else {
+            for row in 0..self.height {
+                let start = row * self.width;
+                let cells = &mut self.buffer.chars[start..start + self.width];
+                let characters = if row < self.view_offset {
+                    let index = self.history_len - self.view_offset + row;
+                    history_row(&self.history, self.history_start + index, self.width)
+                } else {
+                    let start = (row - self.view_offset) * self.width;
+                    &self.backing[start..start + self.width]
+                };
+
+                write_cells(cells, characters);
+            }
+        }
+    }
+
+    /// Shows up to `rows` more of the rows scrolled off the screen, moving the rest of it down
+    ///
+    /// Writing moves the view back to the current screen.
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.set_view_offset((self.view_offset + rows).min(self.history_len));
+    }
+
+    /// Shows up to `rows` less of the rows scrolled off the screen
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.set_view_offset(self.view_offset.saturating_sub(rows));
+    }
+
+    /// Returns the number of rows scrolled off the screen which are shown above the rest of it
+    pub fn view_offset(&self) -> usize {
+        self.view_offset
+    }
+
+    fn set_view_offset(&mut self, view_offset: usize) {
+        if view_offset != self.view_offset {
+            self.view_offset = view_offset;
+            self.mark_dirty(0, self.width * self.height);
+        }
+    }
+
+    /// Keeps the top row, which is about to be scrolled off the screen, in the history
+    fn push_history(&mut self) {
+        let slot = (self.history_start + self.history_len) % SCROLLBACK_ROWS;
+        let start = slot * MAX_WIDTH;
+
+        self.history[start..start + self.width].copy_from_slice(&self.backing[..self.width]);
+        if self.history_len < SCROLLBACK_ROWS {
+            self.history_len += 1;
+        } else {
+            self.history_start = (self.history_start + 1) % SCROLLBACK_ROWS;
+        }
+    }
+
+    /// Records that the cells from `start` to `end` exclusive need to be flushed
+    fn mark_dirty(&mut self, start: usize, end: usize) {
+        self.dirty = match self.dirty {
+            Some((dirty_start, dirty_end)) => Some((dirty_start.min(start), dirty_end.max(end))),
+            None                           => Some((start, end)),
+        };
+    }
+
+    fn write_cell(&mut self, row: usize, col: usize, character: ScreenChar) {
+        let index = row * self.width + col;
+
+        self.backing[index] = character;
+        self.mark_dirty(index, index + 1);
+    }
+
+    /// Returns the number of columns and rows of the screen
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Sets the number of columns and rows of the screen
+    ///
+    /// Panics if they exceed the largest supported text mode.
+    fn set_dimensions(&mut self, width: usize, height: usize) {
+        assert!(are_plausible_dimensions(width, height), "Unsupported text mode {}x{}", width, height);
+
+        self.width = width;
+        self.height = height;
+        self.row_position = height - 1;
+        self.column_position = self.column_position.min(width);
+        self.history_len = 0;
+        self.view_offset = 0;
+
+        for row in 0..height {
+            self.clear_row(row);
+        }
+    }
+
+    /// The cells of the given row, as they are shown after the next flush
+    fn row(&self, row: usize) -> &[ScreenChar] {
+        &self.backing[row * self.width..(row + 1) * self.width]
+    }
+
+    /// Returns the row and the column the next character is written at
+    pub fn position(&self) -> (usize, usize) {
+        (self.row_position, self.column_position)
+    }
+
+    /// Moves the cursor, so that the next character is written at the given row and column
+    ///
+    /// The writer starts on the last row. A newline moves to the next row,
+    /// scrolling only from the last one.
+    /// Panics if the position is outside the screen.
+    pub fn set_position(&mut self, row: usize, col: usize) {
+        assert!(row < self.height && col < self.width, "Position ({}, {}) is outside the screen", row, col);
+
+        self.row_position = row;
+        self.column_position = col;
+        self.word_start = None;
+    }
+
+    /// Clears the screen and moves the cursor to its top left corner
+    pub fn clear(&mut self) {
+        for row in 0..self.height {
+            self.clear_row(row);
+        }
+
+        self.view_offset = 0;
+        self.set_position(0, 0);
+    }
+
+    /// Enables or disables scrolling
+    ///
+    /// While scrolling is disabled, a full last row and the newline character on it start
+    /// over at the beginning of that row, overwriting it, and the other rows stay in place.
+    pub fn set_scroll_enabled(&mut self, enabled: bool) {
+        self.scroll_enabled = enabled;
+    }
+
+    /// Enables or disables word wrapping
+    ///
+    /// In word-wrap mode a word which does not fit on the rest of a line is moved
+    /// to the next one. Words longer than a line are still split.
+    pub fn set_word_wrap(&mut self, enabled: bool) {
+        self.word_wrap = enabled;
+        self.word_start = None;
+    }
+
+    /// Writes a string starting at the given row and column, ignoring and keeping the cursor
+    ///
+    /// Non-printable bytes are written as the character code 0xfe.
+    /// The part of the string which does not fit on the row is not written.
+    /// Panics if the position is outside the screen.
+    pub fn write_at(&mut self, row: usize, col: usize, s: &str) {
+        assert!(row < self.height && col < self.width, "Position ({}, {}) is outside the screen", row, col);
+
+        for (col, byte) in (col..self.width).zip(s.bytes()) {
+            let character = ScreenChar {
+                ascii_character: match byte {
+                    0x20..=0x7e => byte,
+                    _           => 0xfe,
+                },
+                color_code: self.color_code,
+            };
+
+            self.write_cell(row, col, character);
         }
     }
 
+    /// Sets the colors of the characters written from now on
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    /// Returns the foreground and the background color of the characters being written
+    pub fn color(&self) -> (Color, Color) {
+        (self.color_code.foreground(), self.color_code.background())
+    }
+
     /// Writes a string to the VGA text buffer
     /// 
-    /// Simply writes each byte of the given string,
-    /// using the write_byte method
+    /// Simply writes the bytes of the given string,
+    /// using the write_bytes method
     pub fn write_string(&mut self, s: &str) {
-        for b in s.bytes() {
+        self.write_bytes(s.as_bytes());
+    }
+
+    /// Writes each of the given bytes, using the write_byte method
+    ///
+    /// The bytes need not be valid UTF-8: control bytes are interpreted
+    /// and other non-printable ones are shown as the character code 0xfe.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
             self.write_byte(b)
         }
     }
@@ -97,21 +519,124 @@ impl Writer {
     /// 
     /// If the byte is not printable (not in the range 0x20 to 0x7e), 
     /// the character code 0xfe is written.
-    /// The newline character inserts a new line.
+    /// The newline character inserts a new line,
+    /// the tab character advances to the next tab stop and
+    /// the backspace character erases the previous character on the line.
+    /// ANSI escape sequences are interpreted, see `execute_csi`.
     pub fn write_byte(&mut self, byte: u8) {
+        self.set_view_offset(0);
+
+        match self.escape_state {
+            EscapeState::Ground      => self.write_ground_byte(byte),
+            EscapeState::Escape      => self.write_escape_byte(byte),
+            EscapeState::Csi(params) => self.write_csi_byte(params, byte),
+        }
+    }
+
+    fn write_ground_byte(&mut self, byte: u8) {
+        if !byte.is_ascii_graphic() {
+            self.word_start = None;
+        }
+
         match byte {
-            b'\n'       => self.new_line(),
+            b'\n'       => self.line_break(),
+            0x08        => self.backspace(),
+            b'\t'       => self.tab(),
+            0x1b        => self.escape_state = EscapeState::Escape,
             0x20..=0x7e => self.write_regular_byte(byte),
             _           => self.write_regular_byte(0xfe),
         }
     }
 
+    fn write_escape_byte(&mut self, byte: u8) {
+        self.escape_state = match byte {
+            b'[' => EscapeState::Csi(CsiParams::new()),
+            _    => EscapeState::Ground,
+        };
+    }
+
+    /// Collects the parameters of a CSI sequence until its final byte
+    fn write_csi_byte(&mut self, mut params: CsiParams, byte: u8) {
+        match byte {
+            b'0'..=b'9' => params.push_digit(byte - b'0'),
+            b';'        => params.next_param(),
+            0x40..=0x7e => {
+                self.escape_state = EscapeState::Ground;
+                self.execute_csi(byte, &params);
+                return;
+            },
+            _           => {},
+        }
+
+        self.escape_state = EscapeState::Csi(params);
+    }
+
+    /// Executes a complete CSI sequence
+    ///
+    /// Supported are SGR colors (`m` with 0, 30-37, 39, 40-47 and 49),
+    /// `H` (to the row and the column given by its parameters), `A`/`B` (up/down)
+    /// and `C`/`D` (forward/back). The cursor stops at the edges of the screen.
+    /// Any other sequence is consumed without effect.
+    fn execute_csi(&mut self, command: u8, params: &CsiParams) {
+        let last_row = self.height - 1;
+        let last_column = self.width - 1;
+
+        match command {
+            b'm' => {
+                for &code in params.values() {
+                    self.select_graphic_rendition(code);
+                }
+            },
+            b'H' => {
+                let row = usize::from(params.get_or(0, 1)) - 1;
+                let column = usize::from(params.get_or(1, 1)) - 1;
+                self.set_position(row.min(last_row), column.min(last_column));
+            },
+            b'A' => {
+                let n = usize::from(params.get_or(0, 1));
+                self.row_position = self.row_position.saturating_sub(n);
+                self.word_start = None;
+            },
+            b'B' => {
+                let n = usize::from(params.get_or(0, 1));
+                self.row_position = (self.row_position + n).min(last_row);
+                self.word_start = None;
+            },
+            b'C' => {
+                let n = usize::from(params.get_or(0, 1));
+                self.column_position = (self.column_position + n).min(last_column);
+            },
+            b'D' => {
+                let n = usize::from(params.get_or(0, 1));
+                self.column_position = self.column_position.saturating_sub(n);
+            },
+            _    => {},
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, code: u16) {
+        let foreground = self.color_code.foreground();
+        let background = self.color_code.background();
+
+        self.color_code = match code {
+            0       => self.default_color_code,
+            30..=37 => ColorCode::new(ANSI_COLORS[usize::from(code - 30)], background),
+            39      => ColorCode::new(self.default_color_code.foreground(), background),
+            40..=47 => ColorCode::new(foreground, ANSI_COLORS[usize::from(code - 40)]),
+            49      => ColorCode::new(foreground, self.default_color_code.background()),
+            _       => self.color_code,
+        };
+    }
+
     fn write_regular_byte(&mut self, byte: u8) {
-        if self.column_position >= BUFFER_WIDTH {
-            self.new_line();
+        if self.column_position >= self.width {
+            self.wrap_line();
+        }
+        if self.word_wrap && byte != b' ' && self.word_start.is_none() {
+            self.word_start = Some(self.column_position);
         }
 
-        let row = BUFFER_HEIGHT - 1;
+        let row = self.row_position;
         let col = self.column_position;
 
         let character = ScreenChar {
@@ -119,20 +644,90 @@ impl Writer {
             color_code: self.color_code,
         };
 
-        self.buffer.chars[row][col].write(character);
+        self.write_cell(row, col, character);
 
         self.column_position += 1;
     }
 
-    fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let c = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(c);
-            }
+    /// Writes spaces up to the next tab stop, starting a new line if the current one is full
+    fn tab(&mut self) {
+        if self.column_position >= self.width {
+            self.line_break();
+        }
+
+        let next_stop = (self.column_position / TAB_WIDTH + 1) * TAB_WIDTH;
+        while self.column_position < next_stop.min(self.width) {
+            self.write_regular_byte(b' ');
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.column_position == 0 {
+            return;
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
+
+        self.column_position -= 1;
+
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        self.write_cell(self.row_position, self.column_position, blank);
+    }
+
+    /// Continues on a new line once the current one is full
+    ///
+    /// A word which started after the beginning of the line is moved to the new one.
+    fn wrap_line(&mut self) {
+        let start = match self.word_start {
+            Some(start) if start > 0 => start,
+            _                        => return self.line_break(),
+        };
+
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        let row = self.row_position;
+        let length = self.width - start;
+        let mut word = [blank; MAX_WIDTH];
+
+        word[..length].copy_from_slice(&self.row(row)[start..]);
+        for col in start..self.width {
+            self.write_cell(row, col, blank);
+        }
+
+        self.line_break();
+        for (col, &character) in word[..length].iter().enumerate() {
+            self.write_cell(self.row_position, col, character);
+        }
+        self.column_position = length;
+        self.word_start = Some(0);
+    }
+
+    /// Moves to the start of the next row, or of the last one again if scrolling is disabled
+    fn line_break(&mut self) {
+        if self.scroll_enabled || self.row_position + 1 < self.height {
+            self.new_line();
+        } else {
+            self.column_position = 0;
+        }
+    }
+
+    fn new_line(&mut self) {
         self.column_position = 0;
+
+        if self.row_position + 1 < self.height {
+            self.row_position += 1;
+            return;
+        }
+
+        let cells = self.width * self.height;
+
+        self.push_history();
+        self.backing.copy_within(self.width..cells, 0);
+        self.mark_dirty(0, cells);
+        self.clear_row(self.height - 1);
     }
 
     fn clear_row(&mut self, row: usize) {
@@ -140,10 +735,13 @@ impl Writer {
             ascii_character: b' ',
             color_code: self.color_code,
         };
+        let start = row * self.width;
+        let end = start + self.width;
 
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for cell in &mut self.backing[start..end] {
+            *cell = blank;
         }
+        self.mark_dirty(start, end);
     }
 }
 
@@ -154,6 +752,208 @@ impl fmt::Write for Writer {
     }
 }
 
+/// A rectangular region of the screen with its own cursor and color
+///
+/// A window writes to the same screen as the global `WRITER`, but only within its bounds:
+/// it wraps at its right edge and scrolls only its own rows, without keeping them in history.
+/// Windows may overlap each other and the rest of the screen, the last write to a cell shows.
+pub struct Window {
+    row: usize,
+    col: usize,
+    width: usize,
+    height: usize,
+    row_position: usize,
+    column_position: usize,
+    color_code: ColorCode,
+}
+
+impl Window {
+    /// Creates a window of `width` columns and `height` rows with its top left corner
+    /// at the given row and column of the screen. The cursor starts at that corner.
+    ///
+    /// Panics if the window is empty or if it does not fit on the screen.
+    pub fn new(row: usize, col: usize, width: usize, height: usize) -> Self {
+        let (screen_width, screen_height) = dimensions();
+        assert!(
+            width > 0 && height > 0 && col + width <= screen_width && row + height <= screen_height,
+            "Window of {}x{} at ({}, {}) does not fit on the screen", width, height, row, col
+        );
+
+        Window {
+            row,
+            col,
+            width,
+            height,
+            row_position: 0,
+            column_position: 0,
+            color_code: ColorCode::new(Color::LightGray, Color::Black),
+        }
+    }
+
+    /// Sets the colors of the characters written from now on
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    /// Writes a string in the window and shows it
+    ///
+    /// Like `Writer::write_bytes`, non-printable bytes are shown as the character code 0xfe,
+    /// but of the control bytes only the newline is interpreted.
+    pub fn write_string(&mut self, s: &str) {
+        use crate::interrupts::critical_section;
+
+        critical_section(|| {
+            let mut writer = WRITER.lock();
+
+            self.write_bytes_on(&mut writer, s.as_bytes());
+            writer.flush();
+        });
+    }
+
+    /// Blanks the window and moves the cursor to its top left corner
+    pub fn clear(&mut self) {
+        use crate::interrupts::critical_section;
+
+        critical_section(|| {
+            let mut writer = WRITER.lock();
+
+            self.clear_on(&mut writer);
+            writer.flush();
+        });
+    }
+
+    fn write_bytes_on(&mut self, writer: &mut Writer, bytes: &[u8]) {
+        writer.set_view_offset(0);
+
+        for &byte in bytes {
+            match byte {
+                b'\n'       => self.new_line(writer),
+                0x20..=0x7e => self.write_regular_byte(writer, byte),
+                _           => self.write_regular_byte(writer, 0xfe),
+            }
+        }
+    }
+
+    fn write_regular_byte(&mut self, writer: &mut Writer, byte: u8) {
+        if self.column_position >= self.width {
+            self.new_line(writer);
+        }
+
+        let character = ScreenChar {
+            ascii_character: byte,
+            color_code: self.color_code,
+        };
+        writer.write_cell(self.row + self.row_position, self.col + self.column_position, character);
+
+        self.column_position += 1;
+    }
+
+    /// Moves to the start of the next row of the window, scrolling its rows from the last one
+    fn new_line(&mut self, writer: &mut Writer) {
+        self.column_position = 0;
+
+        if self.row_position + 1 < self.height {
+            self.row_position += 1;
+            return;
+        }
+
+        for row in self.row + 1..self.row + self.height {
+            for col in self.col..self.col + self.width {
+                let character = writer.row(row)[col];
+                writer.write_cell(row - 1, col, character);
+            }
+        }
+        self.clear_row_on(writer, self.height - 1);
+    }
+
+    fn clear_on(&mut self, writer: &mut Writer) {
+        for row in 0..self.height {
+            self.clear_row_on(writer, row);
+        }
+
+        self.row_position = 0;
+        self.column_position = 0;
+    }
+
+    /// Blanks the given row of the window
+    fn clear_row_on(&self, writer: &mut Writer, row: usize) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+
+        for col in self.col..self.col + self.width {
+            writer.write_cell(self.row + row, col, blank);
+        }
+    }
+}
+
+impl fmt::Write for Window {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+/// Copies the characters to the cells of the VGA text buffer
+///
+/// It is a single volatile copy rather than a volatile write per cell,
+/// which makes flushing a scrolled screen much cheaper.
+fn write_cells(cells: &mut [Volatile<ScreenChar>], characters: &[ScreenChar]) {
+    assert_eq!(cells.len(), characters.len());
+
+    // `Volatile` is a transparent wrapper, so the cells are laid out like the characters
+    unsafe {
+        core::intrinsics::volatile_copy_nonoverlapping_memory(
+            cells.as_mut_ptr() as *mut ScreenChar,
+            characters.as_ptr(),
+            characters.len(),
+        );
+    }
+}
+
+/// The cells of a row of the history, with `slot` counted from the start of its ring
+fn history_row(history: &[ScreenChar], slot: usize, width: usize) -> &[ScreenChar] {
+    let start = (slot % SCROLLBACK_ROWS) * MAX_WIDTH;
+
+    &history[start..start + width]
+}
+
+fn are_plausible_dimensions(width: usize, height: usize) -> bool {
+    (40..=MAX_WIDTH).contains(&width) && (DEFAULT_HEIGHT..=MAX_HEIGHT).contains(&height)
+}
+
+/// Sets the dimensions of the screen to the ones of the text mode selected by the BIOS
+///
+/// They are read from the BIOS data area, through the mapping of all physical memory
+/// at `physical_memory_offset`. Implausible values are ignored and the default 80x25 is kept.
+/// Text modes larger than 80x25 span more than the single page of the text buffer the
+/// bootloader maps, so the writer then writes through the physical memory mapping.
+///
+/// ## Safety
+///
+/// The complete physical memory must be mapped at `physical_memory_offset`.
+pub unsafe fn init(physical_memory_offset: VirtAddr) {
+    use crate::interrupts::critical_section;
+
+    let columns = (physical_memory_offset + BDA_COLUMNS_ADDRESS).as_ptr::<u16>();
+    let last_row = (physical_memory_offset + BDA_LAST_ROW_ADDRESS).as_ptr::<u8>();
+
+    let width = usize::from(columns.read_volatile());
+    let height = usize::from(last_row.read_volatile()) + 1;
+
+    if !are_plausible_dimensions(width, height) || (width, height) == (DEFAULT_WIDTH, DEFAULT_HEIGHT) {
+        return;
+    }
+
+    critical_section(|| {
+        let mut writer = WRITER.lock();
+
+        writer.buffer = &mut *(physical_memory_offset + BUFFER_ADDRESS).as_mut_ptr::<Buffer>();
+        writer.set_dimensions(width, height);
+    });
+}
+
 /// Prints to the VGA text buffer
 #[macro_export]
 macro_rules! print {
@@ -167,19 +967,206 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// Prints to the VGA text buffer with the given foreground color,
+/// keeping the color of the writer
+#[macro_export]
+macro_rules! cprint {
+    ($color:expr, $($arg:tt)*) => (
+        $crate::vga_buffer::_print_colored($color, format_args!($($arg)*))
+    );
+}
+
+/// Prints to the VGA text buffer with the given foreground color, appending a newline
+#[macro_export]
+macro_rules! cprintln {
+    ($color:expr) => ($crate::cprint!($color, "\n"));
+    ($color:expr, $($arg:tt)*) => ($crate::cprint!($color, "{}\n", format_args!($($arg)*)));
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut writer = WRITER.lock();
+
+        writer.write_fmt(args).unwrap();
+        writer.flush();
+    });
+}
+
+/// Returns the number of columns and rows of the screen
+pub fn dimensions() -> (usize, usize) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| WRITER.lock().dimensions())
+}
+
+/// Scrolls the screen back by up to `rows` rows, see `Writer::scroll_up`
+pub fn scroll_up(rows: usize) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut writer = WRITER.lock();
+
+        writer.scroll_up(rows);
+        writer.flush();
+    });
+}
+
+/// Scrolls the screen forward by up to `rows` rows, see `Writer::scroll_down`
+pub fn scroll_down(rows: usize) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut writer = WRITER.lock();
+
+        writer.scroll_down(rows);
+        writer.flush();
+    });
+}
+
+/// Clears the screen, see `Writer::clear`
+pub fn clear_screen() {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let mut writer = WRITER.lock();
+
+        writer.clear();
+        writer.flush();
+    });
+}
+
+/// Returns the number of rows the screen is scrolled back by
+pub fn view_offset() -> usize {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| WRITER.lock().view_offset())
+}
+
+/// Forcibly unlocks the global writer.
+///
+/// ## Safety
+///
+/// The holder of the lock may be in the middle of writing. This is only sound
+/// when it never runs again, like in a panic handler on a single core with
+/// interrupts disabled, right before halting.
+pub unsafe fn force_unlock() {
+    WRITER.force_unlock();
+}
+
+/// Prints to the VGA text buffer with the given foreground color,
+/// without changing the color of the writer
+#[doc(hidden)]
+pub fn _print_colored(foreground: Color, args: fmt::Arguments) {
+    use core::fmt::Write;
+    use crate::interrupts::critical_section;
 
-    interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
+    critical_section(|| {
+        let mut writer = WRITER.lock();
+        let color_code = writer.color_code;
+
+        writer.color_code = ColorCode::new(foreground, color_code.background());
+        writer.write_fmt(args).unwrap();
+        writer.flush();
+        writer.color_code = color_code;
     });
 }
 
+/// Checks if the given text is currently displayed on a single row of the screen
+#[cfg(test)]
+pub(crate) fn screen_contains(text: &str) -> bool {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let writer = WRITER.lock();
+
+        (0..writer.height)
+        .map(|row| writer.row(row))
+        .any(|row| {
+            row.windows(text.len())
+               .any(|cells| {
+                   cells.iter()
+                        .zip(text.bytes())
+                        .all(|(cell, b)| cell.ascii_character == b)
+               })
+        })
+    })
+}
+
+/// Returns the foreground color of the first occurrence of the given text on a single row of the screen
+#[cfg(test)]
+pub(crate) fn screen_text_color(text: &str) -> Option<Color> {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let writer = WRITER.lock();
+
+        (0..writer.height)
+        .flat_map(|row| writer.row(row).windows(text.len()))
+        .find(|cells| {
+            cells.iter()
+                 .zip(text.bytes())
+                 .all(|(cell, b)| cell.ascii_character == b)
+        })
+        .map(|cells| cells[0].color_code.foreground())
+    })
+}
+
+/// The characters of the screen and the column of the cursor
+#[cfg(test)]
+pub(crate) fn screen_snapshot() -> (alloc::vec::Vec<u8>, usize) {
+    use crate::interrupts::critical_section;
+
+    critical_section(|| {
+        let writer = WRITER.lock();
+        let characters = (0..writer.height)
+                         .flat_map(|row| writer.row(row))
+                         .map(|cell| cell.ascii_character)
+                         .collect();
+
+        (characters, writer.column_position)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec::Vec;
+
+    lazy_static! {
+        /// The address of the buffer test writers write to instead of the VGA text buffer
+        static ref TEST_BUFFER: usize = unsafe {
+            alloc::alloc::alloc_zeroed(alloc::alloc::Layout::new::<Buffer>()) as usize
+        };
+    }
+
+    /// Creates a writer to a zeroed buffer instead of the VGA text buffer
+    ///
+    /// All test writers share the buffer, so only one of them may be used at a time.
+    fn test_writer() -> Writer {
+        let buffer = unsafe {
+            let buffer = *TEST_BUFFER as *mut Buffer;
+            buffer.write_bytes(0, 1);
+            &mut *buffer
+        };
+
+        Writer::with_buffer(ColorCode::new(Color::White, Color::Black), buffer)
+    }
+
+    /// The characters of the given cells
+    fn text_of(cells: &[ScreenChar]) -> Vec<u8> {
+        cells.iter().map(|cell| cell.ascii_character).collect()
+    }
+
+    #[test_case]
+    fn colors_are_converted_from_their_codes() {
+        assert_eq!(Color::from_u8(3), Some(Color::Cyan));
+        assert_eq!(Color::from_u8(16), None);
+        assert!(Color::ALL.iter().all(|&color| Color::from_u8(color as u8) == Some(color)));
+    }
 
     #[test_case]
     fn println_can_print_a_single_line() {
@@ -196,35 +1183,307 @@ mod tests {
     #[test_case]
     fn writing_lines_has_correct_output() {
         use core::fmt::Write;
-        use x86_64::instructions::interrupts;
+        use crate::interrupts::critical_section;
 
         let line = "A string that can fit in a single line";
 
-        interrupts::without_interrupts(|| {
+        critical_section(|| {
             let mut writer = WRITER.lock();
 
             writeln!(writer, "\n{}", line).expect("writeln failed");
 
-            let screen_text = &writer.buffer.chars;
+            let height = writer.height;
 
             assert!(
                 line.chars()
                 .enumerate()
                 .all(|(i, c)| {
-                    let screen_char = screen_text[BUFFER_HEIGHT - 2][i].read();
+                    let screen_char = writer.row(height - 2)[i];
                     let screen_char = char::from(screen_char.ascii_character);
                     c == screen_char
                 })
             );
-            assert!(are_all_blanks(&screen_text[BUFFER_HEIGHT - 2][line.len()..]));
-            assert!(are_all_blanks(&screen_text[BUFFER_HEIGHT - 1]));
+            assert!(are_all_blanks(&writer.row(height - 2)[line.len()..]));
+            assert!(are_all_blanks(writer.row(height - 1)));
+        });
+    }
+
+    #[test_case]
+    fn ansi_color_codes_set_the_foreground() {
+        use crate::interrupts::critical_section;
+
+        critical_section(|| {
+            let mut writer = WRITER.lock();
+            let color = writer.color();
+
+            writer.write_string("\n\x1b[31mred\x1b[0m");
+
+            let row = writer.row(writer.height - 1);
+            assert!(
+                row[..3].iter()
+                        .zip("red".bytes())
+                        .all(|(cell, b)| {
+                            cell.ascii_character == b && cell.color_code.foreground() == Color::Red
+                        })
+            );
+            assert!(are_all_blanks(&row[3..]));
+            assert_eq!(writer.color(), color);
+        });
+    }
+
+    #[test_case]
+    fn tab_advances_to_the_next_tab_stop() {
+        use crate::interrupts::critical_section;
+
+        critical_section(|| {
+            let mut writer = WRITER.lock();
+
+            writer.write_string("\na\tb");
+
+            let row = writer.row(writer.height - 1);
+            assert_eq!(row[0].ascii_character, b'a');
+            assert!(are_all_blanks(&row[1..TAB_WIDTH]));
+            assert_eq!(row[TAB_WIDTH].ascii_character, b'b');
         });
     }
 
-    fn are_all_blanks(screen_chars: &[Volatile<ScreenChar>]) -> bool {
+    #[test_case]
+    fn scrolling_uses_the_set_height() {
+        let mut writer = test_writer();
+        writer.set_dimensions(DEFAULT_WIDTH, 50);
+
+        writer.write_string("first");
+        assert_eq!(writer.row(49)[0].ascii_character, b'f');
+
+        for _ in 0..49 {
+            writer.write_byte(b'\n');
+        }
+        assert_eq!(writer.row(0)[0].ascii_character, b'f');
+
+        writer.write_byte(b'\n');
+        assert!((0..50).all(|row| writer.row(row)[0].ascii_character != b'f'));
+
+        writer.flush();
+        assert!(writer.buffer.chars[50 * DEFAULT_WIDTH..].iter().all(|cell| cell.read().ascii_character == 0));
+    }
+
+    #[test_case]
+    fn cprint_writes_in_the_given_color_and_keeps_the_writer_color() {
+        use crate::interrupts::critical_section;
+
+        let color = critical_section(|| WRITER.lock().color());
+        cprint!(Color::Green, "\n{}", "green");
+
+        critical_section(|| {
+            let writer = WRITER.lock();
+            let row = writer.row(writer.height - 1);
+
+            assert!(
+                row[..5].iter()
+                        .all(|cell| cell.color_code.foreground() == Color::Green)
+            );
+            assert_eq!(writer.color(), color);
+        });
+    }
+
+    #[test_case]
+    fn flushed_screen_matches_the_backing_store() {
+        let mut writer = test_writer();
+
+        for i in 0..30 {
+            writer.write_string("scrolled line\n");
+            writer.set_color(Color::ALL[i % 16], Color::Black);
+            writer.write_string("colored\x08\x08\t.");
+        }
+        writer.flush();
+
+        let cells = writer.width * writer.height;
+        assert!(
+            writer.buffer.chars[..cells]
+            .iter()
+            .zip(&writer.backing[..cells])
+            .all(|(cell, &character)| cell.read() == character)
+        );
+        assert!(writer.dirty.is_none());
+    }
+
+    #[test_case]
+    fn bulk_flush_matches_flushing_cell_by_cell() {
+        /// The characters a flush with a volatile write per cell shows on each cell of the screen
+        fn flushed_cell_by_cell(writer: &Writer) -> Vec<ScreenChar> {
+            (0..writer.height)
+            .flat_map(|row| {
+                if row < writer.view_offset {
+                    let index = writer.history_len - writer.view_offset + row;
+                    history_row(&writer.history, writer.history_start + index, writer.width)
+                } else {
+                    writer.row(row - writer.view_offset)
+                }
+            })
+            .copied()
+            .collect()
+        }
+
+        let mut writer = test_writer();
+        let cells = writer.width * writer.height;
+        let shown = |writer: &Writer| -> Vec<ScreenChar> {
+            writer.buffer.chars[..cells].iter().map(|cell| cell.read()).collect()
+        };
+
+        for i in 0..40 {
+            writer.set_color(Color::ALL[i % 16], Color::ALL[(i + 1) % 8]);
+            writer.write_string("a scrolled line\n");
+        }
+        writer.flush();
+        assert!(shown(&writer) == flushed_cell_by_cell(&writer));
+
+        writer.scroll_up(3);
+        writer.flush();
+        assert!(shown(&writer) == flushed_cell_by_cell(&writer));
+    }
+
+    #[test_case]
+    fn rows_stay_in_place_while_scrolling_is_disabled() {
+        let mut writer = test_writer();
+        let last_row = writer.height - 1;
+
+        writer.write_at(last_row - 1, 2, "pinned");
+        writer.set_scroll_enabled(false);
+        for _ in 0..writer.width {
+            writer.write_byte(b'a');
+        }
+        writer.write_string("bb\nc");
+
+        let row_above = writer.row(last_row - 1);
+        assert!(
+            row_above[2..8].iter()
+                           .zip(b"pinned")
+                           .all(|(cell, &b)| cell.ascii_character == b)
+        );
+        assert_eq!(writer.row(last_row)[0].ascii_character, b'c');
+        assert_eq!(writer.row(last_row)[1].ascii_character, b'b');
+        assert_eq!(writer.row(last_row)[2].ascii_character, b'a');
+    }
+
+    #[test_case]
+    fn word_wrap_moves_words_which_do_not_fit_to_the_next_line() {
+        let mut writer = test_writer();
+        let last_row = writer.height - 1;
+        let width = writer.width;
+
+        writer.set_word_wrap(true);
+        for _ in 0..width - 8 {
+            writer.write_byte(b'a');
+        }
+        writer.write_string(" hello world");
+
+        assert_eq!(text_of(&writer.row(last_row - 1)[width - 7..]), b"hello  ");
+        assert_eq!(text_of(&writer.row(last_row)[..5]), b"world");
+    }
+
+    #[test_case]
+    fn written_bytes_are_interpreted_like_string_bytes() {
+        let mut writer = test_writer();
+        let last_row = writer.height - 1;
+
+        writer.write_bytes(b"ab\ncd\x08\te\x00");
+
+        assert_eq!(text_of(&writer.row(last_row - 1)[..3]), b"ab ");
+        assert_eq!(text_of(&writer.row(last_row)[..11]), b"c       e\xfe ");
+        assert_eq!(writer.column_position, 10);
+    }
+
+    #[test_case]
+    fn writes_proceed_from_the_set_position() {
+        let mut writer = test_writer();
+
+        writer.set_position(5, 10);
+        writer.write_string("X\nY");
+
+        assert_eq!(writer.row(5)[10].ascii_character, b'X');
+        assert_eq!(writer.row(6)[0].ascii_character, b'Y');
+        assert_eq!(writer.position(), (6, 1));
+    }
+
+    #[test_case]
+    fn csi_sequences_move_the_cursor_between_rows() {
+        let mut writer = test_writer();
+        let last_row = writer.height - 1;
+
+        writer.write_string("\x1b[3;5HX");
+        assert_eq!(writer.row(2)[4].ascii_character, b'X');
+        assert_eq!(writer.position(), (2, 5));
+
+        writer.write_string("\x1b[2AY\x1b[5DZ");
+        assert_eq!(writer.row(0)[5].ascii_character, b'Y');
+        assert_eq!(writer.row(0)[1].ascii_character, b'Z');
+
+        writer.write_string("\x1b[A\x1b[100B\x1b[H");
+        assert_eq!(writer.position(), (0, 0));
+        writer.write_string("\x1b[100B");
+        assert_eq!(writer.position(), (last_row, 0));
+    }
+
+    #[test_case]
+    fn window_wraps_and_scrolls_within_its_bounds() {
+        let mut writer = test_writer();
+        let mut window = Window::new(2, 2, 10, 5);
+
+        window.write_bytes_on(&mut writer, b"0123456789abcdef\n1\n2\n3\n4");
+
+        assert_eq!(text_of(&writer.row(2)[2..12]), b"abcdef    ");
+        assert_eq!(text_of(&writer.row(3)[2..12]), b"1         ");
+        assert_eq!(text_of(&writer.row(6)[2..12]), b"4         ");
+
+        let is_in_window = |row: usize, col: usize| (2..7).contains(&row) && (2..12).contains(&col);
+        for row in 0..writer.height {
+            for col in (0..writer.width).filter(|&col| !is_in_window(row, col)) {
+                assert_eq!(writer.row(row)[col].ascii_character, b' ');
+            }
+        }
+    }
+
+    #[test_case]
+    fn clear_blanks_the_screen_and_moves_to_the_top() {
+        let mut writer = test_writer();
+
+        writer.write_string("text\nmore text");
+        writer.clear();
+
+        assert!((0..writer.height).all(|row| writer.row(row).iter().all(|cell| cell.ascii_character == b' ')));
+        assert_eq!(writer.position(), (0, 0));
+    }
+
+    #[test_case]
+    fn scrolled_off_rows_can_be_scrolled_back_to() {
+        let mut writer = test_writer();
+        let (width, height) = writer.dimensions();
+        let shown_at = |writer: &Writer, row: usize| writer.buffer.chars[row * width].read().ascii_character;
+
+        // Line i ends up on row i - 3, after all rows which were on the screen scrolled off
+        for line in 0..height + 2 {
+            writer.write_byte(b'a' + line as u8);
+            writer.write_byte(b'\n');
+        }
+
+        writer.scroll_up(2);
+        writer.flush();
+        assert_eq!(shown_at(&writer, 0), b'b');
+        assert_eq!(shown_at(&writer, 2), b'd');
+
+        writer.scroll_up(SCROLLBACK_ROWS);
+        assert_eq!(writer.view_offset(), height + 2);
+
+        writer.write_byte(b'z');
+        writer.flush();
+        assert_eq!(writer.view_offset(), 0);
+        assert_eq!(shown_at(&writer, 0), b'd');
+    }
+
+    fn are_all_blanks(screen_chars: &[ScreenChar]) -> bool {
         screen_chars
         .iter()
-        .map(|sc| char::from(sc.read().ascii_character))
+        .map(|sc| char::from(sc.ascii_character))
         .all(|c| c == ' ')
     }
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(myos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use myos::{
+    allocator::Locked,
+    interrupts::InterruptController,
+    should_panic,
+    ShouldPanic,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    myos::init(boot_info, InterruptController::Pic).expect("Initialization failed");
+
+    test_main();
+
+    myos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[test_case]
+const RELOCKING_PANICS: ShouldPanic = should_panic!(relocking_panics);
+
+/// Hangs, and so times out, if the lock spins forever
+fn relocking_panics() {
+    let lock = Locked::with_label(0, "test");
+
+    let _guard = lock.lock();
+    let _deadlocked = lock.lock();
+}
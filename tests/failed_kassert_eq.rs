@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(myos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use myos::{
+    kassert_eq,
+    should_panic,
+    ShouldPanic,
+};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    loop {}
+}
+
+#[test_case]
+const FAILED_KASSERT_EQ_PANICS: ShouldPanic = should_panic!(failed_kassert_eq_panics);
+
+/// Prints the expressions `1 + 1` and `3` along with their values 2 and 3 before panicking
+fn failed_kassert_eq_panics() {
+    kassert_eq!(1 + 1, 3, "arithmetic is broken");
+}
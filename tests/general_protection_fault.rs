@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use myos::{
+    gdt,
+    serial_print,
+    serial_println,
+    QemuExitCode,
+    exit_qemu,
+    memory,
+};
+use x86_64::structures::idt::{
+    InterruptDescriptorTable,
+    InterruptStackFrame,
+};
+use bootloader::{
+    BootInfo,
+    entry_point
+};
+
+/// A GDT index far past the few entries of the kernel's GDT
+const INVALID_SELECTOR_INDEX: u16 = 42;
+
+#[cfg(test)]
+entry_point!(main);
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+
+        idt.general_protection_fault
+           .set_handler_fn(test_general_protection_fault_handler);
+
+        idt
+    };
+}
+
+extern "x86-interrupt"
+fn test_general_protection_fault_handler(_: &mut InterruptStackFrame, error_code: u64) {
+    if error_code >> 3 == u64::from(INVALID_SELECTOR_INDEX) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        serial_println!("Error: unexpected selector error code {:#x}\n", error_code);
+        exit_qemu(QemuExitCode::Failure);
+    }
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("general_protection_fault::loading_an_invalid_segment...\t");
+
+    memory::init(boot_info).expect("Memory initialization failed");
+    gdt::init();
+    init_test_idt();
+
+    load_invalid_segment();
+
+    panic!("Execution continued after loading an invalid segment");
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+fn load_invalid_segment() {
+    use x86_64::{
+        PrivilegeLevel,
+        instructions::segmentation::load_ds,
+        structures::gdt::SegmentSelector,
+    };
+
+    let selector = SegmentSelector::new(INVALID_SELECTOR_INDEX, PrivilegeLevel::Ring0);
+    unsafe {
+        load_ds(selector);
+    }
+}
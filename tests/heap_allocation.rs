@@ -11,6 +11,7 @@ use bootloader::{
     BootInfo,
 };
 use core::panic::PanicInfo;
+use myos::interrupts::InterruptController;
 use alloc::{
     boxed::Box,
     vec::Vec,
@@ -19,7 +20,7 @@ use alloc::{
 entry_point!(main);
 
 fn main(boot_info: &'static BootInfo) -> ! {
-    myos::init(boot_info);
+    myos::init(boot_info, InterruptController::Pic).expect("Initialization failed");
 
     test_main();
 
@@ -56,10 +57,54 @@ fn large_allocations_and_mulitple_reallocations_are_handled() {
 
 #[test_case]
 fn allocated_memory_is_freed_and_reused() {
-    use myos::memory::HEAP_SIZE;
+    use myos::memory;
 
-    for i in 0..HEAP_SIZE {
+    // Twice the size of the Heap is allocated in total
+    let allocations = memory::stats().heap_size / 4;
+    for i in 0..allocations {
         let x = Box::new(i);
         assert!(*x == i);
     }
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn allocations_beyond_the_initial_heap_size_grow_the_heap() {
+    use alloc::alloc::{
+        alloc,
+        dealloc,
+        Layout,
+    };
+    use myos::memory;
+
+    let heap_size = memory::stats().heap_size;
+    // Allocations aligned past the page size never get pages of their own, so this one is on the Heap
+    let layout = Layout::from_size_align(2 * heap_size, 2 * memory::PAGE_SIZE).unwrap();
+
+    unsafe {
+        let block = alloc(layout);
+        assert!(!block.is_null());
+
+        block.write_bytes(1, layout.size());
+        let bytes = core::slice::from_raw_parts(block, layout.size());
+        assert_eq!(bytes.iter().map(|&b| usize::from(b)).sum::<usize>(), layout.size());
+
+        dealloc(block, layout);
+    }
+    assert!(memory::stats().heap_size > heap_size);
+}
+
+#[test_case]
+fn heap_usage_returns_to_the_baseline_after_allocations_are_dropped() {
+    use myos::memory;
+
+    let baseline = memory::stats().heap_used;
+    {
+        let vec: Vec<u64> = (0..10_000).collect();
+        let boxes: Vec<Box<u64>> = vec.iter().take(100).map(|&i| Box::new(i)).collect();
+
+        assert!(memory::stats().heap_used > baseline);
+        assert_eq!(*boxes[99], 99);
+    }
+
+    assert_eq!(memory::stats().heap_used, baseline);
+}
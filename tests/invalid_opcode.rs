@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use myos::{
+    serial_print,
+    serial_println,
+    QemuExitCode,
+    exit_qemu,
+};
+use x86_64::structures::idt::{
+    InterruptDescriptorTable,
+    InterruptStackFrame,
+};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.invalid_opcode.set_handler_fn(test_invalid_opcode_handler);
+
+        idt
+    };
+}
+
+extern "x86-interrupt"
+fn test_invalid_opcode_handler(_: &mut InterruptStackFrame) {
+    serial_println!("[ok]");
+
+    exit_qemu(QemuExitCode::Success);
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("invalid_opcode::ud2_is_handled...\t");
+
+    TEST_IDT.load();
+
+    unsafe {
+        asm!("ud2");
+    }
+
+    panic!("Execution continued after an invalid opcode");
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(myos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use myos::{
+    should_panic,
+    util::Once,
+    ShouldPanic,
+};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    loop {}
+}
+
+#[test_case]
+const INITIALISER_USING_THE_ONCE_PANICS: ShouldPanic = should_panic!(initialiser_using_the_once_panics);
+
+/// Panics instead of waiting forever for the initialiser, which is waiting itself
+fn initialiser_using_the_once_panics() {
+    static ONCE: Once<u32> = Once::new();
+
+    ONCE.get_or_init(|| *ONCE.get_or_init(|| 1) + 1);
+}
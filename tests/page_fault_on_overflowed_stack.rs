@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use myos::{
+    gdt,
+    serial_print,
+    serial_println,
+    QemuExitCode,
+    exit_qemu,
+    memory,
+};
+use x86_64::structures::idt::{
+    InterruptDescriptorTable,
+    InterruptStackFrame,
+    PageFaultErrorCode,
+};
+use bootloader::{
+    BootInfo,
+    entry_point
+};
+
+
+#[cfg(test)]
+entry_point!(main);
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+
+        unsafe {
+            idt.page_fault
+               .set_handler_fn(test_page_fault_handler)
+               .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
+            idt.double_fault
+               .set_handler_fn(test_double_fault_handler)
+               .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+
+        idt
+    };
+}
+
+extern "x86-interrupt"
+fn test_page_fault_handler(_: &mut InterruptStackFrame, _: PageFaultErrorCode) {
+    serial_println!("[ok]");
+
+    exit_qemu(QemuExitCode::Success);
+
+    loop {}
+}
+
+extern "x86-interrupt"
+fn test_double_fault_handler(_: &mut InterruptStackFrame, _: u64) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: the page fault escalated to a double fault\n");
+
+    exit_qemu(QemuExitCode::Failure);
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("page_fault_on_overflowed_stack::page_fault_is_handled_on_its_own_stack...\t");
+
+    memory::init(boot_info).expect("Memory initialization failed");
+    gdt::init();
+    init_test_idt();
+
+    stack_overflow();
+
+    panic!("Execution continued after stack overflow");
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+/// Recurses until the stack overflows into its guard page, which causes a page fault.
+/// Pushing the exception frame on the overflowed stack would fault again,
+/// so the page fault is only handled if it switches to its own stack.
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+
+    // prevent tail recursion optimizations
+    volatile::Volatile::new(0).read();
+}
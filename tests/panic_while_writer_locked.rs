@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use myos::{
+    serial_print,
+    serial_println,
+    QemuExitCode,
+    exit_qemu,
+    vga_buffer::WRITER,
+};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("panic_while_writer_locked::panic_is_reported...\t");
+
+    core::mem::forget(WRITER.lock());
+
+    panic!("Panicked while the writer is locked");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::report_panic(info);
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+
+    myos::hlt_loop();
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(myos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use myos::{
+    should_panic,
+    ShouldPanic,
+};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    loop {}
+}
+
+#[test_case]
+const FAILED_ASSERTION_PANICS: ShouldPanic = should_panic!(failed_assertion_panics);
+
+fn failed_assertion_panics() {
+    assert_eq!(0, 1);
+}
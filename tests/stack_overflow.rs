@@ -12,7 +12,6 @@ use myos::{
     QemuExitCode,
     exit_qemu,
     memory,
-    allocator,
 };
 use x86_64::structures::idt::{
     InterruptDescriptorTable,
@@ -59,10 +58,7 @@ fn panic(info: &PanicInfo) -> ! {
 fn main(boot_info: &'static BootInfo) -> ! {
     serial_print!("stack_overflow::stack_overflow...\t");
 
-    memory::init(boot_info);
-    unsafe {
-        allocator::init_heap(memory::HEAP_START, memory::HEAP_SIZE);
-    }
+    memory::init(boot_info).expect("Memory initialization failed");
     gdt::init();
     init_test_idt();
 
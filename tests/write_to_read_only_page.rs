@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use myos::{
+    gdt,
+    serial_print,
+    serial_println,
+    QemuExitCode,
+    exit_qemu,
+    memory,
+};
+use x86_64::{
+    VirtAddr,
+    structures::{
+        idt::{
+            InterruptDescriptorTable,
+            InterruptStackFrame,
+            PageFaultErrorCode,
+        },
+        paging::{
+            Page,
+            PageTableFlags,
+        },
+    },
+};
+use bootloader::{
+    BootInfo,
+    entry_point
+};
+
+/// A page of the Kernel's virtual memory which is not used otherwise
+const PAGE_ADDRESS: u64 = 0x_5555_5557_0000;
+
+#[cfg(test)]
+entry_point!(main);
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+
+        idt.page_fault
+           .set_handler_fn(test_page_fault_handler);
+
+        idt
+    };
+}
+
+extern "x86-interrupt"
+fn test_page_fault_handler(_: &mut InterruptStackFrame, error_code: PageFaultErrorCode) {
+    use x86_64::registers::control::Cr2;
+
+    assert_eq!(Cr2::read(), VirtAddr::new(PAGE_ADDRESS));
+    assert!(error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION));
+    assert!(error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE));
+
+    serial_println!("[ok]");
+
+    exit_qemu(QemuExitCode::Success);
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    myos::test_panic_handler(info)
+}
+
+#[no_mangle]
+fn main(boot_info: &'static BootInfo) -> ! {
+    use x86_64::registers::control::{
+        Cr0,
+        Cr0Flags,
+    };
+
+    serial_print!("write_to_read_only_page::write_to_read_only_page_faults...\t");
+
+    memory::init(boot_info).expect("Memory initialization failed");
+    gdt::init();
+    init_test_idt();
+
+    // Make the kernel respect read-only pages
+    unsafe {
+        Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+    }
+
+    let page: Page = Page::containing_address(VirtAddr::new(PAGE_ADDRESS));
+    let page_ptr = page.start_address().as_mut_ptr::<u64>();
+
+    memory::with_kernel_memory(|mapper, frame_allocator| {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        memory::map_region_huge(mapper, frame_allocator, page.start_address(), memory::PAGE_SIZE, flags)
+            .expect("Mapping the page failed");
+        unsafe {
+            page_ptr.write_volatile(1);
+        }
+
+        memory::update_flags(mapper, page, PageTableFlags::PRESENT)
+            .expect("Updating the flags failed");
+    });
+    assert_eq!(memory::page_flags(page), Some(PageTableFlags::PRESENT));
+
+    unsafe {
+        page_ptr.write_volatile(2);
+    }
+
+    panic!("Writing to a read-only page did not fault");
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}